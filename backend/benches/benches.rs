@@ -1,11 +1,10 @@
-#![feature(test)]
-extern crate test;
+#[macro_use]
+extern crate bencher;
 
-use test::{Bencher, black_box};
+use bencher::{Bencher, black_box};
 
 use backend::*;
 
-#[bench]
 fn subject_combination(b: &mut Bencher) {
     let subject_vec = Subject::Subject::load("../data.json");
 
@@ -20,6 +19,9 @@ fn subject_combination(b: &mut Bencher) {
     let mut sel_subs = black_box(vec![]);
 
     b.iter(|| {
-        let _ = combinator.combinate_subjects(&fix_subs, &mut req_subs, &mut sel_subs);
+        let _ = combinator.combinate_subjects(&fix_subs, &mut req_subs, &mut sel_subs, &Constraint::Constraints::default(), &Tools::Budget::unlimited());
     });
-}
\ No newline at end of file
+}
+
+benchmark_group!(benches, subject_combination);
+benchmark_main!(benches);
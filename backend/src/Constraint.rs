@@ -0,0 +1,175 @@
+use super::Subject::*;
+
+use serde::Deserialize;
+
+/// Hard conditions that every class of a combination must keep.
+///
+/// Times are minutes from 00:00, days are index of `Subject::time_tuple` (0: 월 ~ 6: 일).
+/// Times are checked in slots of `TimeGrid`, same as conflicts between classes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Constraints {
+    /// Days without any class.
+    pub free_days: Vec<usize>,
+    /// No class before this time.
+    pub no_class_before: Option<u32>,
+    /// No class after this time.
+    pub no_class_after: Option<u32>,
+    /// (day, start, end) which must be free.
+    pub blocked_slots: Vec<(usize, u32, u32)>,
+    /// Least credits of a combination, fixed classes included.
+    pub min_credits: Option<u32>,
+    /// Most credits of a combination, fixed classes included.
+    pub max_credits: Option<u32>,
+    /// (start, end, minutes): every day has a free time of at least "minutes" between start and end.
+    pub lunch_break: Option<(u32, u32, u32)>,
+    /// Longest time of back-to-back classes in a day, in minutes.
+    pub max_consecutive: Option<u32>,
+    /// Sections (code, class idx) which are never taken.
+    pub exclude: Vec<(String, usize)>,
+    /// Sections taught by any of these professors are never taken. (part of `Subject::prof` is enough)
+    pub avoid_prof: Vec<String>,
+    /// Elective groups. Subjects of a group don't need to be in "req" or "sel".
+    pub groups: Vec<Group>,
+}
+
+/// Take at least `min` (and at most `max`) subjects of `subjects`.
+/// ex: exactly 2 of 5 is `{"subjects": [..5 codes], "min": 2, "max": 2}`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Group {
+    pub subjects: Vec<String>,
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+/// Most groups in one query.
+pub const MAX_GROUPS: usize = 32;
+
+/// Per-day rules of `Constraints`, in time bits of a `TimeGrid`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DayRule {
+    // (slots inside lunch window, number of free slots needed)
+    lunch: Option<(u64, u32)>,
+    // most occupied slots in a row.
+    max_run: Option<u32>,
+}
+
+impl Constraints {
+    /// Time bits of each day where no class can be.
+    pub fn blocked_bits(&self, grid: &TimeGrid) -> Result<[u64; WEEK_DAYS], &'static str> {
+        let (start, end) = (grid.start_hour * 60, grid.end_hour * 60);
+        let slots = (end - start + grid.slot_minutes - 1) / grid.slot_minutes;
+        let all: u64 = if slots == 64 {!0} else {(1 << slots) - 1};
+        let mut bits = [0; WEEK_DAYS];
+
+        for day in self.free_days.iter() {
+            *bits.get_mut(*day).ok_or("Invalid free day")? = all;
+        }
+        // only slots which are entirely before (or after) the time.
+        if let Some(t) = self.no_class_before {
+            if t > 24 * 60 {return Err("Invalid time");}
+            let n = (t.max(start).min(end) - start) / grid.slot_minutes;
+            let before: u64 = if n == 64 {!0} else {(1 << n) - 1};
+            for b in bits.iter_mut() {
+                *b |= before;
+            }
+        }
+        if let Some(t) = self.no_class_after {
+            if t > 24 * 60 {return Err("Invalid time");}
+            let n = (t.max(start).min(end) - start + grid.slot_minutes - 1) / grid.slot_minutes;
+            let after: u64 = if n >= 64 {0} else {all & !((1 << n) - 1)};
+            for b in bits.iter_mut() {
+                *b |= after;
+            }
+        }
+        for (day, s, e) in self.blocked_slots.iter() {
+            if *s > *e || *e > 24 * 60 {return Err("Invalid time");}
+            // part out of grid has no class anyway.
+            let (s, e) = ((*s).max(start).min(end), (*e).max(start).min(end));
+            let b = bits.get_mut(*day).ok_or("Invalid blocked day")?;
+            *b |= grid.bits(&(s, e)).map_err(|_| "Invalid time")?;
+        }
+        Ok(bits)
+    }
+
+    /// (min, max) credits of a combination. Unlimited side is 0 or `u32::MAX`.
+    pub fn credit_range(&self) -> Result<(u32, u32), &'static str> {
+        let range = (self.min_credits.unwrap_or(0), self.max_credits.unwrap_or(u32::MAX));
+        if range.0 > range.1 {return Err("Invalid credit range");}
+        Ok(range)
+    }
+
+    /// Lunch break and consecutive class rules, checked on time bits of each day.
+    pub fn day_rule(&self, grid: &TimeGrid) -> Result<DayRule, &'static str> {
+        let start = grid.start_hour * 60;
+        let mut rule = DayRule::default();
+        if let Some((s, e, minutes)) = self.lunch_break {
+            if s > e || e > 24 * 60 || minutes > e - s {return Err("Invalid lunch break");}
+            // only slots entirely inside the window.
+            let first = (s.max(start) - start + grid.slot_minutes - 1) / grid.slot_minutes;
+            let last = ((e.max(start) - start) / grid.slot_minutes).min(64);
+            let mut window: u64 = 0;
+            for x in first..last {
+                window |= 1 << x;
+            }
+            // window narrower than a slot can never be free.
+            let slots = (minutes + grid.slot_minutes - 1) / grid.slot_minutes;
+            if slots > last.saturating_sub(first) {return Err("Lunch break is shorter than time slots");}
+            rule.lunch = Some((window, slots));
+        }
+        if let Some(minutes) = self.max_consecutive {
+            rule.max_run = Some(minutes / grid.slot_minutes);
+        }
+        Ok(rule)
+    }
+
+    /// (min, max) of each group.
+    pub fn group_ranges(&self) -> Result<Vec<(usize, usize)>, &'static str> {
+        if self.groups.len() > MAX_GROUPS {return Err("Too many groups");}
+        self.groups.iter().map(|g| {
+            let max = g.max.unwrap_or(g.subjects.len());
+            if g.min > max || g.min > g.subjects.len() {return Err("Invalid group range");}
+            Ok((g.min, max))
+        }).collect()
+    }
+
+    /// true if section is banned by professor. `exclude` is checked by `SubjectCombinator`, which knows class idx.
+    pub fn avoids(&self, sub: &Subject) -> bool {
+        self.avoid_prof.iter().any(|x| x.len() != 0 && sub.prof.contains(x.as_str()))
+    }
+
+    /// true if there is no condition on time of classes.
+    pub fn is_time_free(&self) -> bool {
+        self.free_days.len() == 0 && self.no_class_before.is_none() && self.no_class_after.is_none() && self.blocked_slots.len() == 0
+    }
+}
+
+impl DayRule {
+    pub fn is_empty(&self) -> bool {
+        self.lunch.is_none() && self.max_run.is_none()
+    }
+
+    /// true if time bits of every day keep the rules.
+    /// Adding class never makes a broken day right, so partial timetables can be checked too.
+    pub fn check(&self, week: &[u64; WEEK_DAYS]) -> bool {
+        week.iter().all(|bits| {
+            if let Some((window, slots)) = self.lunch {
+                if !has_run(!bits & window, slots) {return false;}
+            }
+            if let Some(max) = self.max_run {
+                if has_run(*bits, max + 1) {return false;}
+            }
+            true
+        })
+    }
+}
+
+// true if "bits" has "n" set bits in a row.
+fn has_run(mut bits: u64, n: u32) -> bool {
+    if n == 0 {return true;}
+    for _ in 1..n.min(64) {
+        bits &= bits >> 1;
+    }
+    n <= 64 && bits != 0
+}
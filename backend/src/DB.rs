@@ -1,81 +1,672 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use r2d2_redis::{r2d2, RedisConnectionManager};
-use r2d2_redis::redis::Commands;
+use r2d2_redis::redis::{self, Commands};
 
 use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use super::Subject::Subject;
+
+/// Default time to live of a share, 180 days. Refreshed whenever the share is read.
+pub const DEFAULT_TTL: u64 = 180 * 24 * 60 * 60;
+
+/// Version of `Share` record. Records of version 0 have only `comb`.
+pub const SHARE_VERSION: u32 = 1;
+
+/// Shared timetable.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Share {
+    #[serde(default)]
+    pub version: u32,
+    /// "Subject vector" idx of the timetable, in catalog of `catalog`.
+    pub comb: Vec<u32>,
+    /// Fingerprint of the catalog which `comb` points to.
+    #[serde(default)]
+    pub catalog: String,
+    /// (code, class number) of each class in `comb`, to find them in other catalog.
+    #[serde(default)]
+    pub classes: Vec<(String, u8)>,
+    /// Unix time (seconds) when the share expires. `None` for pinned share, which never expires.
+    pub expires: Option<u64>,
+    /// Secret token to update or delete the share. Empty for old share, which cannot be changed.
+    #[serde(default)]
+    pub owner: String,
+}
+
+/// Why a share cannot be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShareError {
+    NotFound,
+    /// Owner token is wrong.
+    Forbidden,
+    /// Timetable has idx which is not in the catalog.
+    UnknownClass,
+    /// Store failed to save.
+    Store(&'static str),
+    /// Share id has wrong check character. (typo)
+    InvalidId,
+    /// Share is from other catalog, and these classes (code, class number) are not in current one.
+    Stale(Vec<(String, u8)>),
+}
+
+/// Subject vector which idx of shares point to. Idx of a subject is its position in the vector,
+/// same as combinations of /api/comb and `No` of /api/data.
+#[derive(Clone)]
+pub struct Catalog {
+    fingerprint: String,
+    classes: HashMap<u32, (String, u8)>,
+    idx: HashMap<(String, u8), u32>,
+}
+
+impl Catalog {
+    pub fn new(subjects: &Vec<Subject>) -> Self {
+        // FNV-1a, to keep fingerprint same over builds and platforms.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for (i, sub) in subjects.iter().enumerate() {
+            let row = format!("{}|{}|{}|{}\n", i, sub.code, sub.class_num, sub.time_place);
+            for b in row.bytes() {
+                hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        let classes: HashMap<u32, (String, u8)> = subjects.iter().enumerate()
+            .map(|(i, x)| (i as u32, (x.code.clone(), x.class_num)))
+            .collect();
+        let idx = classes.iter().map(|(k, v)| (v.clone(), *k)).collect();
+        Catalog {
+            fingerprint: format!("{:016x}", hash),
+            classes: classes,
+            idx: idx,
+        }
+    }
+
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// (code, class number) of each idx, `None` if some idx is not in this catalog.
+    fn classes_of(&self, comb: &Vec<u32>) -> Option<Vec<(String, u8)>> {
+        comb.iter().map(|x| self.classes.get(x).cloned()).collect()
+    }
+
+    /// Moves share to this catalog. Fails with `Stale` of classes which are not in this catalog,
+    /// or `UnknownClass` if idx of old share is not in this catalog.
+    fn resolve(&self, share: &mut Share) -> Result<(), ShareError> {
+        if share.version == 0 {
+            // old share has no catalog, its idx are assumed to be of this catalog.
+            share.classes = self.classes_of(&share.comb).ok_or(ShareError::UnknownClass)?;
+        }
+        else if share.catalog != self.fingerprint {
+            let missing: Vec<(String, u8)> = share.classes.iter().filter(|x| !self.idx.contains_key(x)).cloned().collect();
+            if missing.len() > 0 {
+                return Err(ShareError::Stale(missing));
+            }
+            share.comb = share.classes.iter().map(|x| self.idx[x]).collect();
+        }
+        share.version = SHARE_VERSION;
+        share.catalog = self.fingerprint.clone();
+        Ok(())
+    }
+}
+
+/// What `ShareStore::modify` writes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Put(Share),
+    Delete,
+}
+
+/// Storage of shared timetables by share id.
+pub trait ShareStore: Send + Sync {
+    fn exists(&self, key: &str) -> Result<bool, &'static str>;
+    /// Stores share, replacing old one. It is removed at `share.expires`.
+    fn put(&self, key: &str, share: &Share) -> Result<(), &'static str>;
+    /// Same as `put`, but only if there is no share of `key`, atomically. `false` if `key` is taken.
+    fn put_new(&self, key: &str, share: &Share) -> Result<bool, &'static str>;
+    /// `None` if there is no such key, or it is expired.
+    fn get(&self, key: &str) -> Result<Option<Share>, &'static str>;
+    /// Puts off expiry of share to `expires`, without writing the rest of it.
+    /// Nothing happens if there is no share of `key`, or it never expires (pinned).
+    fn touch(&self, key: &str, expires: u64) -> Result<(), &'static str>;
+    fn delete(&self, key: &str) -> Result<(), &'static str>;
+    /// Reads share of `key`, and writes what `f` returns, atomically: no other write comes in between.
+    /// `f` is called again if other writer came first. `NotFound` if there is no share, or error of `f`.
+    /// Returns the change written.
+    fn modify(&self, key: &str, f: &dyn Fn(&Share) -> Result<Change, ShareError>) -> Result<Change, ShareError>;
+}
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn is_expired(share: &Share) -> bool {
+    share.expires.map_or(false, |t| t <= now())
+}
+
+/// Shares in Redis, as json with expiry of Redis.
+/// Old shares stored as list of idx are still readable.
+pub struct RedisStore {
+    pool: r2d2::Pool<RedisConnectionManager>,
+}
+
+/// Shares in memory of this process. Lost on restart, for development and tests.
+pub struct MemoryStore {
+    shares: Mutex<HashMap<String, Share>>,
+}
+
+/// Shares in embedded sled database file, as json.
+pub struct SledStore {
+    db: sled::Db,
+}
 
-fn make_query_string() -> String
+impl RedisStore {
+    pub fn new(url: &str) -> Result<Self, String> {
+        let manager = RedisConnectionManager::new(url).map_err(|e| e.to_string())?;
+        let pool = r2d2::Pool::builder().build(manager).map_err(|e| e.to_string())?;
+        Ok(RedisStore { pool: pool })
+    }
+
+    pub fn from_pool(pool: r2d2::Pool<RedisConnectionManager>) -> Self {
+        RedisStore { pool: pool }
+    }
+
+    fn conn(&self) -> Result<r2d2::PooledConnection<RedisConnectionManager>, &'static str> {
+        self.pool.get().map_err(|_| "Fail to connect")
+    }
+}
+
+impl ShareStore for RedisStore {
+    fn exists(&self, key: &str) -> Result<bool, &'static str> {
+        self.conn()?.exists(key).map_err(|_| "Err")
+    }
+
+    fn put(&self, key: &str, share: &Share) -> Result<(), &'static str> {
+        let value = serde_json::to_string(share).map_err(|_| "Err")?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().set(key, value).ignore();
+        if let Some(t) = share.expires {
+            pipe.expire_at(key, t as usize).ignore();
+        }
+        pipe.query(&mut *self.conn()?).map_err(|_| "Err")
+    }
+
+    fn put_new(&self, key: &str, share: &Share) -> Result<bool, &'static str> {
+        let value = serde_json::to_string(share).map_err(|_| "Err")?;
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value).arg("NX");
+        if let Some(t) = share.expires {
+            cmd.arg("EX").arg(t.saturating_sub(now()).max(1));
+        }
+        let res: Option<String> = cmd.query(&mut *self.conn()?).map_err(|_| "Err")?;
+        Ok(res.is_some())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Share>, &'static str> {
+        read_redis(&mut *self.conn()?, key)
+    }
+
+    fn touch(&self, key: &str, expires: u64) -> Result<(), &'static str> {
+        // share without TTL is pinned, and stays so.
+        let script = redis::Script::new("if redis.call('TTL', KEYS[1]) > 0 then return redis.call('EXPIREAT', KEYS[1], ARGV[1]) end return 0");
+        let mut conn = self.conn()?;
+        let _: i64 = script.key(key).arg(expires).invoke(&mut *conn).map_err(|_| "Err")?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), &'static str> {
+        self.conn()?.del(key).map_err(|_| "Err")
+    }
+
+    fn modify(&self, key: &str, f: &dyn Fn(&Share) -> Result<Change, ShareError>) -> Result<Change, ShareError> {
+        let mut conn = self.conn().map_err(ShareError::Store)?;
+        let conn: &mut redis::Connection = &mut *conn;
+        let unwatch = |conn: &mut redis::Connection| {
+            let _: redis::RedisResult<()> = redis::cmd("UNWATCH").query(conn);
+        };
+        loop {
+            let _: () = redis::cmd("WATCH").arg(key).query(conn).map_err(|_| ShareError::Store("Err"))?;
+            let change = match read_redis(conn, key) {
+                Ok(Some(share)) => f(&share),
+                Ok(None) => Err(ShareError::NotFound),
+                Err(e) => Err(ShareError::Store(e))
+            };
+            let change = match change {
+                Ok(t) => t,
+                Err(e) => {
+                    unwatch(conn);
+                    return Err(e);
+                }
+            };
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            match &change {
+                Change::Put(share) => {
+                    let value = serde_json::to_string(share).map_err(|_| ShareError::Store("Err"))?;
+                    pipe.set(key, value).ignore();
+                    if let Some(t) = share.expires {
+                        pipe.expire_at(key, t as usize).ignore();
+                    }
+                },
+                Change::Delete => {
+                    pipe.del(key).ignore();
+                }
+            }
+            // None if the share is written by other after WATCH.
+            let done: Option<()> = pipe.query(conn).map_err(|_| ShareError::Store("Err"))?;
+            if done.is_some() {
+                return Ok(change);
+            }
+        }
+    }
+}
+
+fn read_redis(conn: &mut redis::Connection, key: &str) -> Result<Option<Share>, &'static str> {
+    let kind: String = redis::cmd("TYPE").arg(key).query(conn).map_err(|_| "Err")?;
+    match kind.as_str() {
+        "string" => {
+            // expiry is kept by Redis, "expires" of json is not updated by touch.
+            let (value, ttl): (String, i64) = redis::pipe().get(key).ttl(key).query(conn).map_err(|_| "Err")?;
+            let mut share: Share = serde_json::from_str(&value).map_err(|_| "Broken share")?;
+            share.expires = if ttl >= 0 {Some(now() + ttl as u64)} else {None};
+            Ok(Some(share))
+        },
+        "list" => {
+            // old share without expiry. It expires from now on, once it is read.
+            let v: Vec<u32> = conn.lrange(key, 0, -1).map_err(|_| "Err")?;
+            Ok(Some(Share { version: 0, comb: v, catalog: String::new(), classes: Vec::new(), expires: Some(now()), owner: String::new() }))
+        },
+        _ => Ok(None)
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore { shares: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl ShareStore for MemoryStore {
+    fn exists(&self, key: &str) -> Result<bool, &'static str> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn put(&self, key: &str, share: &Share) -> Result<(), &'static str> {
+        let mut shares = self.shares.lock().unwrap();
+        shares.retain(|_, x| !is_expired(x));
+        shares.insert(key.to_string(), share.clone());
+        Ok(())
+    }
+
+    fn put_new(&self, key: &str, share: &Share) -> Result<bool, &'static str> {
+        let mut shares = self.shares.lock().unwrap();
+        shares.retain(|_, x| !is_expired(x));
+        if shares.contains_key(key) {
+            return Ok(false);
+        }
+        shares.insert(key.to_string(), share.clone());
+        Ok(true)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Share>, &'static str> {
+        Ok(self.shares.lock().unwrap().get(key).filter(|x| !is_expired(x)).cloned())
+    }
+
+    fn touch(&self, key: &str, expires: u64) -> Result<(), &'static str> {
+        if let Some(share) = self.shares.lock().unwrap().get_mut(key).filter(|x| !is_expired(x) && x.expires.is_some()) {
+            share.expires = Some(expires);
+        }
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), &'static str> {
+        self.shares.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn modify(&self, key: &str, f: &dyn Fn(&Share) -> Result<Change, ShareError>) -> Result<Change, ShareError> {
+        let mut shares = self.shares.lock().unwrap();
+        let change = match shares.get(key).filter(|x| !is_expired(x)) {
+            Some(share) => f(share)?,
+            None => return Err(ShareError::NotFound)
+        };
+        match &change {
+            Change::Put(share) => {shares.insert(key.to_string(), share.clone());},
+            Change::Delete => {shares.remove(key);}
+        }
+        Ok(change)
+    }
+}
+
+impl SledStore {
+    /// Opens database, and removes expired shares in it.
+    pub fn open(path: &str) -> Result<Self, String> {
+        let db = sled::open(path).map_err(|e| e.to_string())?;
+        for entry in db.iter() {
+            let (key, value) = entry.map_err(|e| e.to_string())?;
+            let expired = serde_json::from_slice::<Share>(&value).map_or(false, |x| is_expired(&x));
+            if expired {
+                db.remove(key).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(SledStore { db: db })
+    }
+}
+
+impl ShareStore for SledStore {
+    fn exists(&self, key: &str) -> Result<bool, &'static str> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn put(&self, key: &str, share: &Share) -> Result<(), &'static str> {
+        let value = serde_json::to_vec(share).map_err(|_| "Err")?;
+        self.db.insert(key, value).map_err(|_| "Err")?;
+        self.db.flush().map_err(|_| "Err")?;
+        Ok(())
+    }
+
+    fn put_new(&self, key: &str, share: &Share) -> Result<bool, &'static str> {
+        let value = serde_json::to_vec(share).map_err(|_| "Err")?;
+        // expired share can be replaced.
+        let old = self.db.get(key).map_err(|_| "Err")?;
+        if let Some(t) = &old {
+            if serde_json::from_slice::<Share>(t).map_or(true, |x| !is_expired(&x)) {
+                return Ok(false);
+            }
+        }
+        let swapped = self.db.compare_and_swap(key, old, Some(value)).map_err(|_| "Err")?;
+        self.db.flush().map_err(|_| "Err")?;
+        Ok(swapped.is_ok())
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Share>, &'static str> {
+        match self.db.get(key).map_err(|_| "Err")? {
+            Some(t) => {
+                let share: Share = serde_json::from_slice(&t).map_err(|_| "Broken share")?;
+                if is_expired(&share) {Ok(None)} else {Ok(Some(share))}
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn touch(&self, key: &str, expires: u64) -> Result<(), &'static str> {
+        // not flushed, expiry lost by crash is only a little earlier.
+        self.db.update_and_fetch(key, |old| {
+            let old = old?;
+            match serde_json::from_slice::<Share>(old) {
+                Ok(mut share) if !is_expired(&share) && share.expires.is_some() => {
+                    share.expires = Some(expires);
+                    serde_json::to_vec(&share).ok()
+                },
+                _ => Some(old.to_vec())
+            }
+        }).map_err(|_| "Err")?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), &'static str> {
+        self.db.remove(key).map_err(|_| "Err")?;
+        self.db.flush().map_err(|_| "Err")?;
+        Ok(())
+    }
+
+    fn modify(&self, key: &str, f: &dyn Fn(&Share) -> Result<Change, ShareError>) -> Result<Change, ShareError> {
+        loop {
+            let old = self.db.get(key).map_err(|_| ShareError::Store("Err"))?.ok_or(ShareError::NotFound)?;
+            let share: Share = serde_json::from_slice(&old).map_err(|_| ShareError::Store("Broken share"))?;
+            if is_expired(&share) {
+                return Err(ShareError::NotFound);
+            }
+            let change = f(&share)?;
+            let new = match &change {
+                Change::Put(share) => Some(serde_json::to_vec(share).map_err(|_| ShareError::Store("Err"))?),
+                Change::Delete => None
+            };
+            // Err if the share is written by other after get.
+            let swapped = self.db.compare_and_swap(key, Some(old), new).map_err(|_| ShareError::Store("Err"))?;
+            if swapped.is_ok() {
+                self.db.flush().map_err(|_| ShareError::Store("Err"))?;
+                return Ok(change);
+            }
+        }
+    }
+}
+
+/// Opens store from config string.
+///
+/// - "memory"
+/// - "sled:<path>"
+/// - "redis://<host>/"
+pub fn open_store(config: &str) -> Result<Box<dyn ShareStore>, String> {
+    if config == "memory" {
+        Ok(Box::new(MemoryStore::new()))
+    }
+    else if config.starts_with("sled:") {
+        Ok(Box::new(SledStore::open(&config["sled:".len()..])?))
+    }
+    else if config.starts_with("redis://") {
+        Ok(Box::new(RedisStore::new(config)?))
+    }
+    else {
+        Err(format!("Unknown share store {}", config))
+    }
+}
+
+/// Secret owner token of 32 alphanumeric characters.
+fn make_owner_token() -> String
 {
     const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
                             abcdefghijklmnopqrstuvwxyz\
-                            0123456789)(*^$@!~";
-    const PASSWORD_LEN: usize = 7;
+                            0123456789";
     let mut rng = rand::thread_rng();
-
-    let password: String = (0..PASSWORD_LEN)
-        .map(|_| {
-            let idx = rng.gen_range(0, CHARSET.len());
-            // This is safe because `idx` is in range of `CHARSET`
-            char::from(unsafe { *CHARSET.get_unchecked(idx) })
-        })
-        .collect();
-    password
+    (0..32).map(|_| char::from(CHARSET[rng.gen_range(0, CHARSET.len())])).collect()
 }
 
-pub fn add_share<'a, 'b>(pool: &'a r2d2::Pool<RedisConnectionManager>, value : &Vec<u32>) -> Result<String, &'b str>
+/// Compares tokens in time not depending on where they differ.
+fn is_owner(share: &Share, token: &str) -> bool
 {
-    let mut conn = match pool.get() {
-        Ok(t) => t,
-        Err(_) => {return Err("Fail to connect");}
-    };
+    let (a, b) = (share.owner.as_bytes(), token.as_bytes());
+    a.len() > 0 && a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |x, (p, q)| x | (p ^ q)) == 0
+}
+
+/// Characters of share id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdAlphabet {
+    /// 0-9, A-Z, a-z. Case sensitive.
+    Base62,
+    /// Crockford's base32, 0-9 and A-Z without I, L, O, U.
+    /// Case insensitive, and I, L are read as 1, O as 0.
+    Crockford,
+}
+
+impl IdAlphabet {
+    fn chars(&self) -> &'static [u8] {
+        match self {
+            IdAlphabet::Base62 => b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz",
+            IdAlphabet::Crockford => b"0123456789ABCDEFGHJKMNPQRSTVWXYZ",
+        }
+    }
 
-    let mut password = make_query_string();
-    
-    let mut exist : u32 = conn.exists(&password).unwrap_or(1);
-    while exist == 1
-    {
-        password = make_query_string();
-        match conn.exists(&password) {
-            Ok(t) => exist = t,
-            Err(_) => return Err(&"Err"),
+    fn value(&self, c: char) -> Option<usize> {
+        let c = match self {
+            IdAlphabet::Base62 => c,
+            IdAlphabet::Crockford => match c.to_ascii_uppercase() {
+                'O' => '0',
+                'I' | 'L' => '1',
+                c => c
+            }
         };
+        self.chars().iter().position(|x| *x as char == c)
     }
+}
 
-    let _x : u32;
-    match conn.rpush(&password, value.clone()) {
-        Ok(t) => {_x = t;},
-        Err(_) => return Err(&"Err"),
-    };
+/// Form of share id: `len` random characters, and a check character if `checksum`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdFormat {
+    pub alphabet: IdAlphabet,
+    pub len: usize,
+    pub checksum: bool,
+}
+
+impl Default for IdFormat {
+    fn default() -> Self {
+        IdFormat {
+            alphabet: IdAlphabet::Base62,
+            len: 7,
+            checksum: false,
+        }
+    }
+}
 
-    Ok(password)
+impl IdFormat {
+    pub fn new(alphabet: IdAlphabet, len: usize, checksum: bool) -> Result<Self, String> {
+        if len == 0 || len > 64 {
+            return Err(format!("Invalid id length {}", len));
+        }
+        Ok(IdFormat {
+            alphabet: alphabet,
+            len: len,
+            checksum: checksum,
+        })
+    }
+
+    pub fn generate(&self) -> String {
+        let chars = self.alphabet.chars();
+        let mut rng = rand::thread_rng();
+        let mut id: Vec<usize> = (0..self.len).map(|_| rng.gen_range(0, chars.len())).collect();
+        if self.checksum {
+            id.push(self.check_value(&id));
+        }
+        id.into_iter().map(|x| chars[x] as char).collect()
+    }
+
+    /// Key in `store` of share `id`. Id as it is is tried first, because ids of older format
+    /// can look like ids of this format. (ex: "aBcdEfg" is a Crockford id "ABCDEFG")
+    /// If it is not in store, same as `parse`.
+    pub fn find(&self, store: &dyn ShareStore, id: &str) -> Result<String, ShareError> {
+        if store.exists(id).map_err(ShareError::Store)? {
+            return Ok(id.to_string());
+        }
+        self.parse(id)
+    }
+
+    /// Id of this format in canonical form, and `InvalidId` if its check character is wrong.
+    /// Other ids (from older format) are kept as they are.
+    pub fn parse(&self, id: &str) -> Result<String, ShareError> {
+        let values: Option<Vec<usize>> = id.chars().map(|x| self.alphabet.value(x)).collect();
+        let total = self.len + if self.checksum {1} else {0};
+        match values {
+            Some(mut v) if v.len() == total => {
+                if self.checksum {
+                    let check = v.pop().unwrap();
+                    if check != self.check_value(&v) {
+                        return Err(ShareError::InvalidId);
+                    }
+                    v.push(check);
+                }
+                let chars = self.alphabet.chars();
+                Ok(v.into_iter().map(|x| chars[x] as char).collect())
+            },
+            _ => Ok(id.to_string())
+        }
+    }
+
+    /// Luhn mod N check character, which catches any one wrong character and most swaps of neighbours.
+    fn check_value(&self, values: &[usize]) -> usize {
+        let n = self.alphabet.chars().len();
+        let sum: usize = values.iter().rev().enumerate().map(|(i, x)| {
+            let t = x * if i % 2 == 0 {2} else {1};
+            t / n + t % n
+        }).sum();
+        (n - sum % n) % n
+    }
 }
 
-pub fn get_share(pool: &r2d2::Pool<RedisConnectionManager>, key : &String) -> Option<Vec<u32>>
+/// Most tries to find unused share id.
+const ID_TRIES: usize = 16;
+
+/// Saves timetable of `catalog`, which expires `ttl` seconds later. Pinned if `ttl` is `None`.
+/// Returns share id and the share, with owner token to change it later.
+pub fn add_share(store: &dyn ShareStore, catalog: &Catalog, format: &IdFormat, value : &Vec<u32>, ttl: Option<u64>) -> Result<(String, Share), &'static str>
 {
-    let mut conn = match pool.get() {
-        Ok(t) => t,
-        Err(_) => {return None}
+    let classes = catalog.classes_of(value).ok_or("Unknown class")?;
+    let share = Share {
+        version: SHARE_VERSION,
+        comb: value.clone(),
+        catalog: catalog.fingerprint.clone(),
+        classes: classes,
+        expires: ttl.map(|t| now() + t),
+        owner: make_owner_token()
     };
+    for _ in 0..ID_TRIES {
+        let id = format.generate();
+        if store.put_new(&id, &share)? {
+            return Ok((id, share));
+        }
+    }
+    Err("No free id")
+}
 
-    let ans : Option<Vec<u32>> = match conn.lrange(key, 0, -1) {
-        Ok(t) => {
-            let v : Vec<u32> = t;
-            if v.len()==0 
-            {
-                None
-            } else {
-                Some(v)
-            }
-        },
-        Err(_) => None,
-    };
-    ans
+/// Reads share, with `comb` of `catalog`. Share from older catalog is moved to `catalog`.
+/// Its expiry is put off to `ttl` seconds from now, unless it is pinned.
+pub fn get_share(store: &dyn ShareStore, catalog: &Catalog, key : &String, ttl: u64) -> Result<Share, ShareError>
+{
+    let mut share = store.get(key).map_err(ShareError::Store)?.ok_or(ShareError::NotFound)?;
+    let migrated = share.version != SHARE_VERSION || share.catalog != catalog.fingerprint;
+    catalog.resolve(&mut share)?;
+    if share.expires.is_some() {
+        share.expires = Some(now() + ttl);
+    }
+    // still readable even if saving fails.
+    if migrated {
+        // moved again from the share as it is now, not to undo update or delete of the owner.
+        let _ = store.modify(key, &|x| {
+            let mut x = x.clone();
+            catalog.resolve(&mut x)?;
+            x.expires = x.expires.map(|_| now() + ttl);
+            Ok(Change::Put(x))
+        });
+    }
+    else if let Some(t) = share.expires {
+        let _ = store.touch(key, t);
+    }
+    Ok(share)
 }
 
-pub fn del_share(pool: r2d2::Pool<RedisConnectionManager>, key : &String)
+/// Replaces timetable of share with `value` of `catalog`, if `token` is its owner token.
+/// Pinned or not is changed only if `pin` is given, and expiry is put off as `get_share`.
+pub fn update_share(store: &dyn ShareStore, catalog: &Catalog, key : &String, token: &str, value: &Vec<u32>, pin: Option<bool>, ttl: u64) -> Result<Share, ShareError>
 {
-    let mut conn = match pool.get() {
-        Ok(t) => t,
-        Err(_) => {return}
-    };
-    let _ans : u32 = conn.del(key).unwrap();
-}
\ No newline at end of file
+    let classes = catalog.classes_of(value).ok_or(ShareError::UnknownClass)?;
+    let change = store.modify(key, &|x| {
+        if !is_owner(x, token) {
+            return Err(ShareError::Forbidden);
+        }
+        let pinned = pin.unwrap_or(x.expires.is_none());
+        Ok(Change::Put(Share {
+            version: SHARE_VERSION,
+            comb: value.clone(),
+            catalog: catalog.fingerprint.clone(),
+            classes: classes.clone(),
+            expires: if pinned {None} else {Some(now() + ttl)},
+            owner: x.owner.clone()
+        }))
+    })?;
+    match change {
+        Change::Put(share) => Ok(share),
+        Change::Delete => Err(ShareError::NotFound)
+    }
+}
+
+/// Deletes share, if `token` is its owner token.
+pub fn delete_share(store: &dyn ShareStore, key : &String, token: &str) -> Result<(), ShareError>
+{
+    store.modify(key, &|x| if is_owner(x, token) {Ok(Change::Delete)} else {Err(ShareError::Forbidden)})?;
+    Ok(())
+}
+
+pub fn del_share(store: &dyn ShareStore, key : &String)
+{
+    let _ = store.delete(key);
+}
@@ -0,0 +1,189 @@
+use super::Subject::*;
+
+/// Scores a single timetable. Higher score means a better timetable.
+///
+/// `table` is every class in the combination, in any order.
+pub trait Scorer: Send + Sync {
+    fn score(&self, table: &[&Subject]) -> f64;
+
+    /// Upper bound of score of any timetable which contains every class of `partial`.
+    /// Used to prune search, so `None` (unknown) is always safe.
+    fn bound(&self, _partial: &[&Subject]) -> Option<f64> {
+        None
+    }
+}
+
+/// Prefers timetables that use fewer days in the week.
+pub struct FewestDays;
+
+/// Prefers timetables with less idle time between classes of the same day (in hours).
+pub struct LeastGap;
+
+/// Prefers timetables whose earliest class of the week starts late (in hours).
+pub struct LatestStart;
+
+/// Prefers timetables whose last class of the week finishes early (in hours).
+pub struct EarliestFinish;
+
+/// Prefers timetables with more credits.
+pub struct TotalCredits;
+
+/// Prefers timetables with more classes taught by any of `profs`. (part of `Subject::prof` is enough)
+pub struct PreferProf {
+    pub profs: Vec<String>,
+}
+
+/// Weighted sum of other scorers.
+///
+/// Weights are multiplied with the raw score of each term, so a negative weight
+/// turns a preference around (ex: `-1.0` on `TotalCredits` prefers fewer credits).
+pub struct Weighted {
+    terms: Vec<(f64, Box<dyn Scorer>)>,
+}
+
+impl Scorer for FewestDays {
+    fn score(&self, table: &[&Subject]) -> f64 {
+        let days = day_spans(table).iter().filter(|x| x.len() != 0).count();
+        -(days as f64)
+    }
+
+    // adding class never reduce days.
+    fn bound(&self, partial: &[&Subject]) -> Option<f64> {
+        Some(self.score(partial))
+    }
+}
+
+impl Scorer for LeastGap {
+    fn score(&self, table: &[&Subject]) -> f64 {
+        let mut gap: u32 = 0;
+        for spans in day_spans(table).iter() {
+            for w in spans.windows(2) {
+                gap += w[1].0 - w[0].1;
+            }
+        }
+        -(gap as f64) / 60.0
+    }
+}
+
+impl Scorer for LatestStart {
+    fn score(&self, table: &[&Subject]) -> f64 {
+        let start = day_spans(table).iter().filter_map(|x| x.first()).map(|x| x.0).min();
+        match start {
+            Some(t) => t as f64 / 60.0,
+            None => 24.0,
+        }
+    }
+
+    // adding class never makes start later.
+    fn bound(&self, partial: &[&Subject]) -> Option<f64> {
+        Some(self.score(partial))
+    }
+}
+
+impl Scorer for EarliestFinish {
+    fn score(&self, table: &[&Subject]) -> f64 {
+        let end = day_spans(table).iter().filter_map(|x| x.last()).map(|x| x.1).max();
+        match end {
+            Some(t) => -(t as f64) / 60.0,
+            None => 0.0,
+        }
+    }
+
+    // adding class never makes finish earlier.
+    fn bound(&self, partial: &[&Subject]) -> Option<f64> {
+        Some(self.score(partial))
+    }
+}
+
+impl Scorer for TotalCredits {
+    fn score(&self, table: &[&Subject]) -> f64 {
+        table.iter().map(|x| x.credit as u32).sum::<u32>() as f64
+    }
+}
+
+impl Scorer for PreferProf {
+    fn score(&self, table: &[&Subject]) -> f64 {
+        table.iter().filter(|x| self.profs.iter().any(|p| p.len() != 0 && x.prof.contains(p.as_str()))).count() as f64
+    }
+}
+
+impl Weighted {
+    pub fn new() -> Self {
+        Weighted { terms: Vec::new() }
+    }
+
+    pub fn add(mut self, weight: f64, scorer: Box<dyn Scorer>) -> Self {
+        self.terms.push((weight, scorer));
+        self
+    }
+
+    /// Builds weighted scorer from (name, weight) pairs given by client.
+    ///
+    /// Names: "days", "gap", "start", "finish", "credit".
+    pub fn from_names<'a, I>(weights: I) -> Result<Self, String>
+    where I: IntoIterator<Item = (&'a String, &'a f64)> {
+        let mut scorer = Weighted::new();
+        for (name, weight) in weights {
+            match by_name(name) {
+                Some(t) => scorer = scorer.add(*weight, t),
+                None => return Err(format!("Unknown scorer {}", name)),
+            }
+        }
+        Ok(scorer)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.len() == 0
+    }
+}
+
+impl Scorer for Weighted {
+    fn score(&self, table: &[&Subject]) -> f64 {
+        self.terms.iter().map(|(w, s)| w * s.score(table)).sum()
+    }
+
+    // only known when every term has bound and non-negative weight.
+    fn bound(&self, partial: &[&Subject]) -> Option<f64> {
+        let mut sum = 0.0;
+        for (w, s) in self.terms.iter() {
+            if *w == 0.0 {continue;}
+            if *w < 0.0 {return None;}
+            sum += w * s.bound(partial)?;
+        }
+        Some(sum)
+    }
+}
+
+/// Returns built-in scorer of given name.
+pub fn by_name(name: &str) -> Option<Box<dyn Scorer>> {
+    match name {
+        "days" => Some(Box::new(FewestDays)),
+        "gap" => Some(Box::new(LeastGap)),
+        "start" => Some(Box::new(LatestStart)),
+        "finish" => Some(Box::new(EarliestFinish)),
+        "credit" => Some(Box::new(TotalCredits)),
+        _ => None,
+    }
+}
+
+// Class time of each day, sorted and merged. (minutes)
+fn day_spans(table: &[&Subject]) -> [Vec<(u32, u32)>; WEEK_DAYS] {
+    let mut spans: [Vec<(u32, u32)>; WEEK_DAYS] = Default::default();
+    for sub in table.iter() {
+        for (day, times) in sub.time_tuple.iter().enumerate() {
+            spans[day].extend(times.iter().cloned());
+        }
+    }
+    for day in spans.iter_mut() {
+        day.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(day.len());
+        for &(s, e) in day.iter() {
+            match merged.last_mut() {
+                Some(last) if s <= last.1 => last.1 = last.1.max(e),
+                _ => merged.push((s, e)),
+            }
+        }
+        *day = merged;
+    }
+    spans
+}
@@ -1,20 +1,109 @@
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use regex;
 
 use lazy_static::lazy_static;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de;
 
 
 lazy_static! {
-static ref RE_DATE : regex::Regex = regex::Regex::new(r"([월화수목금토일])+(\w{2}:\w{2})-(\w{2}:\w{2})\((\w{2}\s?-\s?\w*)\)").unwrap();
+static ref RE_DATE : regex::Regex = regex::Regex::new(r"([^\W\d_]+)(\w{2}:\w{2})-(\w{2}:\w{2})\((\w{2}\s?-\s?\w*)\)").unwrap();
 static ref RE_TIME : regex::Regex = regex::Regex::new(r"(\w{2}):(\w{2})").unwrap();
 }
 
-const BLOCK_SIZE: u32 = 30;//min
-const BLOCK_START: u32 = 60*9;//min
+/// Number of days in time table. (월 ~ 일)
+pub const WEEK_DAYS: usize = 7;
+
+const DAYS: [&str; WEEK_DAYS] = ["월", "화", "수", "목", "금", "토", "일"];
+
+/// Why time and place string of a class cannot be parsed.
+/// Each variant holds the part of string (or time) that is wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubjectParseError {
+    /// Time is not "HH:MM", or class ends before it starts.
+    BadTimeFormat(String),
+    /// Two times of a class overlap each other.
+    OverlappingSlots(String),
+    /// Time is out of `TimeGrid`.
+    OutOfRange(String),
+    /// Day is not one of 월 ~ 일.
+    UnknownDay(String),
+}
+
+impl fmt::Display for SubjectParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubjectParseError::BadTimeFormat(t) => write!(f, "Bad time format: {}", t),
+            SubjectParseError::OverlappingSlots(t) => write!(f, "Overlapping times: {}", t),
+            SubjectParseError::OutOfRange(t) => write!(f, "Time out of range: {}", t),
+            SubjectParseError::UnknownDay(t) => write!(f, "Unknown day: {}", t),
+        }
+    }
+}
+
+impl Error for SubjectParseError {}
+
+/// Range and resolution of time bits (`Subject::time_bit`).
+///
+/// Each day is split into slots of `slot_minutes` from `start_hour` to `end_hour`,
+/// and slot n is bit n of the day. A day can have at most 64 slots.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeGrid {
+    pub start_hour: u32,
+    pub end_hour: u32,
+    pub slot_minutes: u32,
+}
+
+impl TimeGrid {
+    pub fn new(start_hour: u32, end_hour: u32, slot_minutes: u32) -> Result<Self, String> {
+        if start_hour >= end_hour || end_hour > 24 {
+            return Err(format!("Invalid hours {}-{}", start_hour, end_hour));
+        }
+        if slot_minutes == 0 || ((end_hour - start_hour) * 60 + slot_minutes - 1) / slot_minutes > 64 {
+            return Err(format!("Invalid slot {}min (at most 64 slots in a day)", slot_minutes));
+        }
+        Ok(TimeGrid {
+            start_hour: start_hour,
+            end_hour: end_hour,
+            slot_minutes: slot_minutes,
+        })
+    }
+
+    /// Time bits of (start, end) in minutes.
+    /// Partly covered slots are counted as occupied, so classes that overlap always conflict.
+    pub fn bits(&self, time: &(u32, u32)) -> Result<u64, SubjectParseError> {
+        let (start, end) = (self.start_hour * 60, self.end_hour * 60);
+        let text = format!("{:02}:{:02}-{:02}:{:02}", time.0 / 60, time.0 % 60, time.1 / 60, time.1 % 60);
+        if time.0 > time.1 {
+            return Err(SubjectParseError::BadTimeFormat(text));
+        }
+        if time.0 < start || time.1 > end {
+            return Err(SubjectParseError::OutOfRange(format!("{} (grid {:02}:00-{:02}:00)", text, self.start_hour, self.end_hour)));
+        }
+        let first = (time.0 - start) / self.slot_minutes;
+        let last = (time.1 - start + self.slot_minutes - 1) / self.slot_minutes;
+        let mut b: u64 = 0;
+        for x in first..last {
+            b |= 1 << x;
+        }
+        Ok(b)
+    }
+}
+
+impl Default for TimeGrid {
+    /// 08:00 ~ 22:00, 15 minutes.
+    fn default() -> Self {
+        TimeGrid {
+            start_hour: 8,
+            end_hour: 22,
+            slot_minutes: 15,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Subject
@@ -27,14 +116,36 @@ pub struct Subject
     pub credit: u8,
     pub time_place: String,
     pub place: Vec<String>,
-    pub time_tuple: [Vec<(u32, u32)>; 5],
-    pub time_bit: [u64; 5]
+    #[serde(deserialize_with = "week")]
+    pub time_tuple: [Vec<(u32, u32)>; WEEK_DAYS],
+    #[serde(deserialize_with = "week")]
+    pub time_bit: [u64; WEEK_DAYS],
+    /// Lecture or lab (LT_PRAC_NM of the catalog). Empty if unknown.
+    #[serde(default)]
+    pub kind: String,
+    /// Sections (code, class number) of which one must be taken with this section.
+    /// ex: lecture section links to its lab sections. Links go one way, from lecture to lab.
+    #[serde(default)]
+    pub links: Vec<(String, u8)>
 }
 
 impl Subject {
     pub fn new(number: u32, code: String, class_num: u8, class_name: String, prof: String, credit: u8, time_place: String) -> Self {
-        let (place, time, bits) = time_and_place(time_place.clone()).unwrap();
-        Subject {
+        Subject::with_grid(number, code, class_num, class_name, prof, credit, time_place, &TimeGrid::default())
+    }
+
+    pub fn with_grid(number: u32, code: String, class_num: u8, class_name: String, prof: String, credit: u8, time_place: String, grid: &TimeGrid) -> Self {
+        Subject::try_with_grid(number, code, class_num, class_name, prof, credit, time_place, grid).unwrap()
+    }
+
+    /// Same as `new`, but returns error instead of panic when `time_place` is malformed.
+    pub fn try_new(number: u32, code: String, class_num: u8, class_name: String, prof: String, credit: u8, time_place: String) -> Result<Self, SubjectParseError> {
+        Subject::try_with_grid(number, code, class_num, class_name, prof, credit, time_place, &TimeGrid::default())
+    }
+
+    pub fn try_with_grid(number: u32, code: String, class_num: u8, class_name: String, prof: String, credit: u8, time_place: String, grid: &TimeGrid) -> Result<Self, SubjectParseError> {
+        let (place, time, bits) = time_and_place(time_place.clone(), grid)?;
+        Ok(Subject {
             number: number,
             code: code,
             class_num: class_num,
@@ -44,8 +155,10 @@ impl Subject {
             time_place: time_place,
             place: place,
             time_tuple: time,
-            time_bit: bits
-        }
+            time_bit: bits,
+            kind: String::new(),
+            links: Vec::new()
+        })
     }
 
     pub fn save(subjects: &Vec<Subject>, file_name: &str) {
@@ -54,15 +167,44 @@ impl Subject {
         buffer.write(&dump_string.into_bytes()[..]).unwrap();
     }
 
+    /// Subjects which cannot be encoded with default grid are left out. (see `load_with_grid`)
     pub fn load(path: &str) -> Vec<Self> {
+        Subject::load_with_grid(path, &TimeGrid::default()).0
+    }
+
+    /// Load subjects, and encode time bits again with `grid`.
+    /// (saved time bits may come from other grid.)
+    /// Subjects which cannot be encoded are returned separately with their error.
+    pub fn load_with_grid(path: &str, grid: &TimeGrid) -> (Vec<Self>, Vec<(Self, SubjectParseError)>) {
         let file = File::open(path).unwrap();
         let reader = BufReader::new(file);
-        serde_json::from_reader(reader).unwrap()
+        let subjects: Vec<Subject> = serde_json::from_reader(reader).unwrap();
+        let mut loaded = Vec::with_capacity(subjects.len());
+        let mut skipped = Vec::new();
+        for mut sub in subjects.into_iter() {
+            match sub.encode(grid) {
+                Ok(_) => loaded.push(sub),
+                Err(e) => skipped.push((sub, e))
+            }
+        }
+        (loaded, skipped)
+    }
+
+    /// Set time bits from time tuples, with `grid`.
+    pub fn encode(&mut self, grid: &TimeGrid) -> Result<(), SubjectParseError> {
+        for (day, times) in self.time_tuple.iter().enumerate() {
+            let mut b: u64 = 0;
+            for t in times.iter() {
+                b |= grid.bits(t)?;
+            }
+            self.time_bit[day] = b;
+        }
+        Ok(())
     }
 
-    pub fn to_array(&self) -> String {
+    /// `idx`: position of this subject in the vector, which /api/comb and shares use as index.
+    pub fn to_array(&self, idx: usize) -> String {
         let mut time_string = String::from("{");
-        const DAYS: [&str; 5] = ["월", "화", "수", "목","금"];
         for (idx, elem) in self.time_tuple.iter().enumerate() {
             if elem.len() == 0 {continue;}
             time_string += &format!("\"{}\":[", DAYS[idx]);
@@ -85,14 +227,14 @@ impl Subject {
         }
         place_string += &String::from("]");
         format!("[{},\"{}\",{},\"{}\",\"{}\",{},{},{}]", 
-        &(self.number-1), &self.code, &self.class_num, &self.class_name, &self.prof, &self.credit, place_string, time_string)
+        &idx, &self.code, &self.class_num, &self.class_name, &self.prof, &self.credit, place_string, time_string)
     }
 
     pub fn zipped_json(subjects: &Vec<Subject>) -> String {
         //let data_array = Vec::new();
         let mut s = String::from("[");
         for (i, e) in subjects.iter().enumerate() {
-            s += &e.to_array();
+            s += &e.to_array(i);
             if i != subjects.len()-1 {s += &String::from(",")}
         }
         s += &String::from("]");
@@ -100,48 +242,66 @@ impl Subject {
     }
 }
 
-fn time_to_num(time_str: &str) -> Result<u32, Box<dyn Error>>
+fn time_to_num(time_str: &str) -> Result<u32, SubjectParseError>
 {   
-    let cap = RE_TIME.captures(time_str).unwrap();
-    let h: u32 = cap[1].parse::<u32>()?;
-    let m: u32 = cap[2].parse::<u32>()?;
+    let bad = || SubjectParseError::BadTimeFormat(time_str.to_string());
+    let cap = RE_TIME.captures(time_str).ok_or_else(bad)?;
+    let h: u32 = cap[1].parse::<u32>().map_err(|_| bad())?;
+    let m: u32 = cap[2].parse::<u32>().map_err(|_| bad())?;
+    if h > 24 || m >= 60 || h*60+m > 24*60 {
+        return Err(bad());
+    }
     
     Ok(h*60+m)
 }
 
-fn time_to_bit(time_tuple: &(u32, u32)) -> Result<u64, Box<dyn Error>>
-{
-    let mut b : u64 = 0;
-    for x in (time_tuple.0-BLOCK_START)/BLOCK_SIZE..(time_tuple.1-BLOCK_START)/BLOCK_SIZE
-    {
-        b = b + (1 << x);
-    }
-
-    Ok(b)
-}
-
-fn time_and_place(time_place_str: String) -> Result<(Vec<String>, [Vec<(u32, u32)>; 5], [u64; 5]), Box<dyn Error>>
+fn time_and_place(time_place_str: String, grid: &TimeGrid) -> Result<(Vec<String>, [Vec<(u32, u32)>; WEEK_DAYS], [u64; WEEK_DAYS]), SubjectParseError>
 {
     let mut place = Vec::new();
-    let mut time_tuple: [Vec<(u32, u32)>; 5] = [Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()];
-    let mut time_bit: [u64; 5] = [0, 0, 0, 0, 0];
+    let mut time_tuple: [Vec<(u32, u32)>; WEEK_DAYS] = Default::default();
+    let mut time_bit: [u64; WEEK_DAYS] = [0; WEEK_DAYS];
+    // text between times should be only separators.
+    let leftover = |text: &str| {
+        let text = text.trim_matches(|c: char| c.is_whitespace() || c == ',');
+        if text.len() == 0 {Ok(())} else {Err(SubjectParseError::BadTimeFormat(text.to_string()))}
+    };
+    let mut end = 0;
     for cap in RE_DATE.captures_iter(&time_place_str[..])
     {
+        let whole = cap.get(0).unwrap();
+        leftover(&time_place_str[end..whole.start()])?;
+        end = whole.end();
         place.push(cap[4].to_string());
         let t = (time_to_num(&cap[2])?, time_to_num(&cap[3])?);
-        let bit = time_to_bit(&t)?;
-        let i: usize;
-        match &cap[1] {
-            "월" => i = 0,
-            "화" => i = 1,
-            "수" => i = 2,
-            "목" => i = 3,
-            "금" => i = 4,
-            _ => continue
+        let bit = grid.bits(&t)?;
+        // "월수09:00-..." is taken as the last day, as before.
+        let days: Option<Vec<usize>> = cap[1].chars().map(|c| DAYS.iter().position(|x| x.starts_with(c))).collect();
+        let i: usize = match days.as_ref().and_then(|x| x.last()) {
+            Some(t) => *t,
+            None => return Err(SubjectParseError::UnknownDay(cap[1].to_string()))
         };
+        // compare in minutes. bits of back-to-back times can share a slot.
+        if time_tuple[i].iter().any(|x| x.0 < t.1 && t.0 < x.1) {
+            return Err(SubjectParseError::OverlappingSlots(cap[0].to_string()));
+        }
         time_tuple[i].push(t);
-        if time_bit[i] | bit != time_bit[i] + bit {panic!();}
         time_bit[i] = time_bit[i] | bit;
     }
+    leftover(&time_place_str[end..])?;
     Ok((place, time_tuple, time_bit))
-}
\ No newline at end of file
+}
+
+// Reads per-day array of any length up to WEEK_DAYS.
+// data.json saved before weekend support has only 5 days(월 ~ 금).
+fn week<'de, D, T>(deserializer: D) -> Result<[T; WEEK_DAYS], D::Error>
+where D: Deserializer<'de>, T: Deserialize<'de> + Default {
+    let days: Vec<T> = Vec::deserialize(deserializer)?;
+    if days.len() > WEEK_DAYS {
+        return Err(de::Error::invalid_length(days.len(), &"at most 7 days"));
+    }
+    let mut week: [T; WEEK_DAYS] = Default::default();
+    for (i, e) in days.into_iter().enumerate() {
+        week[i] = e;
+    }
+    Ok(week)
+}
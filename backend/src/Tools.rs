@@ -1,11 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, BinaryHeap};
+use std::cmp::Ordering;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
 
 use super::Subject::*;
+use super::Score::Scorer;
+use super::Constraint::{Constraints, DayRule};
 
 use std::fmt;
+#[cfg(feature = "simd")]
 use packed_simd::{u64x4};
 
 use lifeguard::*;
+use rayon::prelude::*;
+use serde::Serialize;
 
 #[derive(Copy, Clone)]
 pub struct BitArray {
@@ -51,6 +59,7 @@ impl Into<[u64; 4]> for BitArray {
     }
 }
 
+#[cfg(feature = "simd")]
 impl Into<u64x4> for BitArray {
     #[inline(always)]
     fn into(self) -> u64x4 {
@@ -67,6 +76,67 @@ impl fmt::Debug for BitArray {
 }
 
 type SingleCombination = Vec<usize>;
+/// Interchangeable classes ("Subject vector" idx) for each class of a timetable.
+pub type GroupedCombination = Vec<Vec<usize>>;
+
+// Plans with less leaves (before pruning) than this are searched on single thread.
+const PARALLEL_LEAVES: usize = 1 << 16;
+
+// Walker reports expanded nodes to Budget once in this many nodes.
+const BUDGET_BATCH: u64 = 256;
+
+/// Limit of work for one query, shared by every thread of the search.
+///
+/// When the deadline passes or `max_nodes` search nodes are expanded, search stops
+/// and returns what it has found so far; `is_truncated` tells it happened.
+/// Both are checked once in 256 nodes, so search can go a little over.
+#[derive(Debug, Default)]
+pub struct Budget {
+    deadline: Option<Instant>,
+    max_nodes: Option<u64>,
+    nodes: AtomicU64,
+    truncated: AtomicBool,
+}
+
+impl Budget {
+    /// No limit.
+    pub fn unlimited() -> Self {
+        Budget::default()
+    }
+
+    /// Stop after `time` from now.
+    pub fn with_deadline(mut self, time: Duration) -> Self {
+        self.deadline = Some(Instant::now() + time);
+        self
+    }
+
+    pub fn with_max_nodes(mut self, nodes: u64) -> Self {
+        self.max_nodes = Some(nodes);
+        self
+    }
+
+    /// true if some search with this budget has stopped before the end.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Stop searches with this budget, as if it is used up.
+    pub fn stop(&self) {
+        self.truncated.store(true, AtomicOrdering::Relaxed);
+    }
+
+    // Count expanded nodes. false if budget is used up.
+    fn spend(&self, nodes: u64) -> bool {
+        if self.is_truncated() {return false;}
+        let total = self.nodes.fetch_add(nodes, AtomicOrdering::Relaxed) + nodes;
+        let exceeded = self.max_nodes.map_or(false, |x| total >= x)
+            || self.deadline.map_or(false, |x| Instant::now() >= x);
+        if exceeded {
+            self.truncated.store(true, AtomicOrdering::Relaxed);
+        }
+        !exceeded
+    }
+}
 /// Struct for combinate subjects with conditions.
 /// 
 /// # Algorithm for check conflict: 
@@ -75,208 +145,996 @@ type SingleCombination = Vec<usize>;
 /// 2. For each time(table) that has been counted as array index, check conflict each other.
 /// 3. Save these conflict boolean to BitArray.
 /// 4. When we need to check conflict, we call BitArray corresponding to index then masking using SIMD(Performance!!)
+///     SIMD needs nightly Rust and "simd" feature. Without it, same masking is done on each u64.
+///
+/// Each row of conflict bits is `chunks` BitArrays (256 time patterns per BitArray), sized at construction.
+/// Usual catalogs have less than 256 patterns, so a row is single BitArray and checked by one SIMD operation.
 pub struct SubjectCombinator {
     // conflict_array: pre-calculated conflict boolean.
-    conflict_array: [BitArray; 256],
-    // code_to_subject: mapping code and class number to index in "conflict_array".
-    // Shape of {class code: [conflict_array idx of each class]}.
-    code_to_subject: HashMap<String, Vec<u8>>,
-    // code_to_num: mapping code and class number to index in "Subject vector".
+    // Row of idx is conflict_array[idx*chunks..(idx+1)*chunks].
+    conflict_array: Vec<BitArray>,
+    // chunks: number of BitArray in a row.
+    chunks: usize,
+    // patterns: time bits of each conflict_array idx.
+    patterns: Vec<[u64; WEEK_DAYS]>,
+    // code_to_subject: mapping code and choice of class to index in "conflict_array".
+    // Shape of {class code: [conflict_array idx of each choice]}.
+    // A choice is a class, or a class with its linked class. Same as classes if there is no link.
+    code_to_subject: HashMap<String, Vec<u32>>,
+    // code_to_num: mapping code and choice of class to index in "Subject vector".
     code_to_num: HashMap<String, Vec<usize>>,
+    // code_to_pair: "Subject vector" idx of linked class taken with each choice.
+    code_to_pair: HashMap<String, Vec<Option<usize>>>,
+    // code_to_section: mapping code and class idx (as in fixed subjects) to index in "Subject vector".
+    code_to_section: HashMap<String, Vec<usize>>,
+    // section_bits: conflict_array idx of each subject.
+    section_bits: Vec<u32>,
+    // links: "Subject vector" idx of linked classes of each subject.
+    links: Vec<Vec<usize>>,
+    // linked: true if some class links to the subject.
+    linked: Vec<bool>,
     // subjects: Storage for Subjects.
     subjects: Vec<Subject>,
+    // grid: time grid which time bits of subjects are encoded with.
+    grid: TimeGrid,
 
     // object pool for SingleCombination.
     obj_pool: Pool<Vec<usize>>,
     comb_pool: Pool<Vec<RcRecycled<SingleCombination>>>,
-    bit_pool: Pool<Vec<BitArray>>,
 }
 
 impl Clone for SubjectCombinator {
     fn clone(&self) -> Self {
         SubjectCombinator {
             conflict_array: self.conflict_array.clone(),
+            chunks: self.chunks,
+            patterns: self.patterns.clone(),
             code_to_subject: self.code_to_subject.clone(),
             code_to_num: self.code_to_num.clone(),
+            code_to_pair: self.code_to_pair.clone(),
+            code_to_section: self.code_to_section.clone(),
+            section_bits: self.section_bits.clone(),
+            links: self.links.clone(),
+            linked: self.linked.clone(),
             subjects: self.subjects.clone(),
+            grid: self.grid,
             obj_pool: pool().with(StartingSize(256)).with(Supplier(|| Vec::with_capacity(30))).build(),
             comb_pool: pool().with(StartingSize(2)).with(Supplier(|| Vec::with_capacity(1000))).build(),
-            bit_pool: pool().with(StartingSize(2)).with(Supplier(|| Vec::with_capacity(1000))).build()
         }
     }
 }
 
 impl SubjectCombinator {
     pub fn new(subs: Vec<Subject>) -> Self {
-        let mut subject_map: HashMap<String, Vec<&Subject>> = HashMap::new();
+        SubjectCombinator::with_grid(subs, TimeGrid::default())
+    }
+
+    /// `subs` should be encoded with `grid`. (see `Subject::encode`)
+    ///
+    /// Section with `Subject::links` is only taken together with one of linked sections,
+    /// and linked sections are only taken together with a section linking to them.
+    pub fn with_grid(subs: Vec<Subject>, grid: TimeGrid) -> Self {
+        let mut subject_map: HashMap<String, Vec<(u8, usize)>> = HashMap::new();
+        let mut time_map: HashMap<[u64; WEEK_DAYS], u32> = HashMap::new();
+        let mut idx_maps: HashMap<String, Vec<u32>> = HashMap::new();
         let mut code_to_num: HashMap<String, Vec<usize>> = HashMap::new();
-        let mut time_map: HashMap<[u64;5], u8> = HashMap::new();
-        let mut idx_maps: HashMap<String, Vec<u8>> = HashMap::new();
-        let mut idx: u8 = 0;
+        let mut code_to_pair: HashMap<String, Vec<Option<usize>>> = HashMap::new();
+        let mut code_to_section: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut idx: u32 = 0;
         for (subs_idx, e) in subs.iter().enumerate() {
             match subject_map.get_mut(&e.code) {
-                Some(v) => v.push(e),
-                None => { subject_map.insert(e.code.clone(), vec![e]); }
+                Some(v) => v.push((e.class_num, subs_idx)),
+                None => { subject_map.insert(e.code.clone(), vec![(e.class_num, subs_idx)]); }
             }
             if !time_map.contains_key(&e.time_bit) {
                 time_map.insert(e.time_bit.clone(), idx);
                 idx += 1;
             }
-            match code_to_num.get_mut(&e.code) {
-                Some(v) => v.push(subs_idx),
-                None => { code_to_num.insert(e.code.clone(), vec![subs_idx]); }
-            }
         }
+        let section_bits: Vec<u32> = subs.iter().map(|x| *time_map.get(&x.time_bit).unwrap()).collect();
 
+        // links to sections which are not in "subs" are dropped.
+        let sections: HashMap<(&str, u8), usize> = subs.iter().enumerate().map(|(i, x)| ((x.code.as_str(), x.class_num), i)).collect();
+        let links: Vec<Vec<usize>> = subs.iter().map(|x| {
+            x.links.iter().filter_map(|(code, class_num)| sections.get(&(code.as_str(), *class_num)).cloned()).collect()
+        }).collect();
+        let mut linked = vec![false; subs.len()];
+        for t in links.iter().flatten() {
+            linked[*t] = true;
+        }
+
+        // every map is in order of class number.
+        // a choice of subject is a section, or a section with one of its links.
         for (k, e) in subject_map.iter_mut() {
-            e.sort_unstable_by_key(|x| x.class_num);
-            idx_maps.insert(k.clone(), e.iter().map(|x| *time_map.get(&x.time_bit).unwrap() ).collect());
+            e.sort_unstable();
+            let mut bits = Vec::with_capacity(e.len());
+            let mut nums = Vec::with_capacity(e.len());
+            let mut pairs = Vec::with_capacity(e.len());
+            for (_, num) in e.iter().filter(|x| !linked[x.1]) {
+                if links[*num].len() == 0 {
+                    bits.push(section_bits[*num]);
+                    nums.push(*num);
+                    pairs.push(None);
+                    continue;
+                }
+                for pair in links[*num].iter() {
+                    if is_conflict(&subs[*num].time_bit, &subs[*pair].time_bit) {continue;}
+                    let mut time = subs[*num].time_bit;
+                    for (t, b) in time.iter_mut().zip(subs[*pair].time_bit.iter()) {
+                        *t |= b;
+                    }
+                    if !time_map.contains_key(&time) {
+                        time_map.insert(time, idx);
+                        idx += 1;
+                    }
+                    bits.push(*time_map.get(&time).unwrap());
+                    nums.push(*num);
+                    pairs.push(Some(*pair));
+                }
+            }
+            idx_maps.insert(k.clone(), bits);
+            code_to_num.insert(k.clone(), nums);
+            code_to_pair.insert(k.clone(), pairs);
+            code_to_section.insert(k.clone(), e.iter().map(|x| x.1).collect());
+        }
+
+        let mut patterns = vec![[0; WEEK_DAYS]; time_map.len()];
+        for (k, e) in time_map.iter() {
+            patterns[*e as usize] = *k;
         }
 
-        let mut conflict_bit = [BitArray::zero(); 256];
+        let chunks = ((time_map.len() + 255) / 256).max(1);
+        let mut conflict_bit = vec![BitArray::zero(); time_map.len() * chunks];
         for (k1, e1) in time_map.iter() {
             for (k2, e2) in time_map.iter() {
                 if is_conflict(k1, k2) {
-                    conflict_bit[*e1 as usize].set(*e2, true);
-                    conflict_bit[*e2 as usize].set(*e1, true);
+                    set_bit(&mut conflict_bit[*e1 as usize * chunks..(*e1 as usize + 1) * chunks], *e2);
+                    set_bit(&mut conflict_bit[*e2 as usize * chunks..(*e2 as usize + 1) * chunks], *e1);
                 }
             }
         }
         SubjectCombinator {
             conflict_array: conflict_bit,
+            chunks: chunks,
+            patterns: patterns,
             code_to_subject: idx_maps,
             code_to_num: code_to_num,
+            code_to_pair: code_to_pair,
+            code_to_section: code_to_section,
+            section_bits: section_bits,
+            links: links,
+            linked: linked,
             subjects: subs,
+            grid: grid,
             obj_pool: pool().with(StartingSize(256)).with(Supplier(|| Vec::with_capacity(30))).build(),
             comb_pool: pool().with(StartingSize(2)).with(Supplier(|| Vec::with_capacity(1000))).build(),
-            bit_pool: pool().with(StartingSize(2)).with(Supplier(|| Vec::with_capacity(1000))).build()
         }
     }
 
-    pub fn combinate_subjects(&self, fixsubs: &Vec<(String, /*Index, not class number*/usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>)
-     -> Result<Option<Recycled<Vec<RcRecycled<SingleCombination>>>>, &str> {
-        // first, sort reqired subjects(reqsubs) and selected subjects(selsubs) by use key as number of classes (which sharing subject code)
+    /// Every valid combination, in search order. Stops early if `budget` is used up. (see `Budget`)
+    pub fn combinate_subjects(&self, fixsubs: &Vec<(String, /*Index, not class number*/usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>, constraints: &Constraints, budget: &Budget)
+     -> Result<Option<Recycled<'_, Vec<RcRecycled<SingleCombination>>>>, &str> {
+        // plan sorts reqired subjects(reqsubs) and selected subjects(selsubs) by use key as number of classes (which sharing subject code)
         // this reduce execution time
-        let mut req_no_sub_flag = false;
-        reqsubs.sort_unstable_by_key(|x| match self.code_to_subject.get(x) {
-            Some(t) => t.len(),
-            None => {
-                req_no_sub_flag = true;
-                0
+        let plan = match self.plan(fixsubs, reqsubs, selsubs, constraints)? {
+            Some(t) => t,
+            None => return Ok(None)
+        };
+
+        let mut sub_comb_list = self.comb_pool.new();
+        let mut walkers = self.split(plan, budget);
+        if walkers.len() == 1 {
+            let walker = &mut walkers[0];
+            while let Some(comb) = walker.next_with(|_| true) {
+                let mut new_sub = self.obj_pool.new_rc();
+                new_sub.extend_from_slice(comb);
+                sub_comb_list.push(new_sub);
             }
-        } );
-        if req_no_sub_flag {
-            return Err("Invalid required subject");
         }
+        else {
+            // branches are collected in order, so result is same as sequential search.
+            let branches: Vec<Vec<SingleCombination>> = walkers.into_par_iter().map(|mut walker| {
+                let mut found = Vec::new();
+                while let Some(comb) = walker.next_with(|_| true) {
+                    found.push(comb.to_vec());
+                }
+                found
+            }).collect();
+            for comb in branches.iter().flatten() {
+                let mut new_sub = self.obj_pool.new_rc();
+                new_sub.extend_from_slice(comb);
+                sub_comb_list.push(new_sub);
+            }
+        }
+
+        if sub_comb_list.len() == 0 {
+            Ok(None)
+        }
+        else {
+            Ok(Some(sub_comb_list))
+        }
+    }
+
+    // Walkers which cover the plan together, in search order.
+    // Big plans are split at each class of the first required subject, to be walked in parallel.
+    fn split<'a>(&'a self, plan: Plan<'a>, budget: &'a Budget) -> Vec<Walker<'a>> {
+        let first = match plan.slots.first() {
+            Some(t) if !t.optional && t.bits.len() > 1 => t.bits.len(),
+            _ => return vec![Walker::new(self, plan, budget)]
+        };
+        // upper bound of leaves. small plans are faster without threads.
+        let leaves = plan.slots.iter().fold(1usize, |acc, x| acc.saturating_mul(x.bits.len() + x.optional as usize));
+        if leaves < PARALLEL_LEAVES {
+            return vec![Walker::new(self, plan, budget)]
+        }
+        (0..first).map(|x| Walker::branch(self, plan.clone(), budget, x)).collect()
+    }
+
+    // Conflict bits of time pattern idx.
+    #[inline(always)]
+    fn conflict_row(&self, idx: u32) -> &[BitArray] {
+        &self.conflict_array[idx as usize * self.chunks..(idx as usize + 1) * self.chunks]
+    }
+
+    /// Lazily yields every valid combination, one at a time, walking conflict bits depth-first.
+    ///
+    /// Nothing is searched before `next` is called, so caller can count, stream or stop early
+    /// without holding every combination in memory.
+    /// Each combination has fixed classes first, then one class of each required subject,
+    /// then classes of selected subjects which are taken.
+    pub fn iter_combinations<'a>(&'a self, fixsubs: &Vec<(String, usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>, constraints: &Constraints, budget: &'a Budget)
+     -> Result<Combinations<'a>, &'a str> {
+        let walker = self.plan(fixsubs, reqsubs, selsubs, constraints)?.map(|plan| Walker::new(self, plan, budget));
+        Ok(Combinations { walker: walker })
+    }
+
+    /// Number of valid combinations, without keeping any of them.
+    pub fn count_combinations(&self, fixsubs: &Vec<(String, usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>, constraints: &Constraints, budget: &Budget)
+     -> Result<usize, &str> {
+        let plan = match self.plan(fixsubs, reqsubs, selsubs, constraints)? {
+            Some(t) => t,
+            None => return Ok(0)
+        };
+        let count = self.split(plan, budget).into_par_iter().map(|mut walker| {
+            let mut count = 0;
+            while walker.next_with(|_| true).is_some() {
+                count += 1;
+            }
+            count
+        }).sum();
+        Ok(count)
+    }
+
+    /// Returns only the best `k` combinations under `scorer`, best first.
+    ///
+    /// Unlike `combinate_subjects`, this never holds more than `k` combinations.
+    /// Branches whose `Scorer::bound` cannot beat current k-th combination are skipped.
+    /// Combinations with same score are kept in search order.
+    pub fn combinate_top_k(&self, fixsubs: &Vec<(String, usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>, constraints: &Constraints, budget: &Budget, k: usize, scorer: &dyn Scorer)
+     -> Result<Option<Vec<SingleCombination>>, &str> {
+        let plan = match self.plan(fixsubs, reqsubs, selsubs, constraints)? {
+            Some(t) => t,
+            None => return Ok(None)
+        };
+        if k == 0 {
+            return Ok(None)
+        }
+
+        let mut best: BinaryHeap<Ranked> = BinaryHeap::new();
+        let mut seq: usize = 0;
+        let mut walker = Walker::new(self, plan, budget);
+        loop {
+            let leaf = walker.next_with(|partial| {
+                // prune only when heap is full, and the branch cannot be better than the worst one.
+                if best.len() < k {return true;}
+                let table: Vec<&Subject> = partial.iter().map(|x| &self.subjects[*x]).collect();
+                match scorer.bound(&table) {
+                    Some(b) => b > best.peek().unwrap().score,
+                    None => true
+                }
+            });
+            let comb = match leaf {
+                Some(t) => t,
+                None => break
+            };
+            let score = self.score(comb, scorer);
+            seq += 1;
+            if best.len() == k {
+                if score <= best.peek().unwrap().score {continue;}
+                best.pop();
+            }
+            best.push(Ranked { score: score, seq: seq, comb: comb.to_vec() });
+        }
+
+        if best.len() == 0 {
+            Ok(None)
+        }
+        else {
+            Ok(Some(best.into_sorted_vec().into_iter().map(|x| x.comb).collect()))
+        }
+    }
+
+    /// Every timetable shape once, with interchangeable classes.
+    ///
+    /// Classes of a subject with same time and credits (usually differ only by professor) are one choice.
+    /// Each grouped combination has a list of "Subject vector" idx for each class of the timetable,
+    /// in same order as `combinate_subjects`; any one of each list can be taken.
+    /// Lecture taken with its linked lab is not grouped with others.
+    pub fn combinate_grouped(&self, fixsubs: &Vec<(String, usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>, constraints: &Constraints, budget: &Budget)
+     -> Result<Option<Vec<GroupedCombination>>, &str> {
+        let mut plan = match self.plan(fixsubs, reqsubs, selsubs, constraints)? {
+            Some(t) => t,
+            None => return Ok(None)
+        };
+        self.group_choices(&mut plan);
+
+        let branches: Vec<Vec<GroupedCombination>> = self.split(plan, budget).into_par_iter().map(|mut walker| {
+            let mut found = Vec::new();
+            while walker.next_with(|_| true).is_some() {
+                found.push(walker.grouped());
+            }
+            found
+        }).collect();
+        let grouped: Vec<GroupedCombination> = branches.into_iter().flatten().collect();
+
+        if grouped.len() == 0 {
+            Ok(None)
+        }
+        else {
+            Ok(Some(grouped))
+        }
+    }
+
+    // Mark choices which have same time, credits and no linked class as an earlier choice of the slot.
+    // Banned classes are never the first one.
+    fn group_choices(&self, plan: &mut Plan) {
+        let banned = &plan.banned;
+        let is_banned = |num: usize| banned.len() != 0 && banned[num];
+        for slot in plan.slots.iter() {
+            let mut same = vec![None; slot.bits.len()];
+            let mut alternatives: Vec<Vec<usize>> = (0..slot.bits.len()).map(|x| vec![x]).collect();
+            let mut first: HashMap<(u32, u8), usize> = HashMap::new();
+            for c in 0..slot.bits.len() {
+                if slot.pairs[c].is_some() || is_banned(slot.nums[c]) {continue;}
+                let key = (slot.bits[c], self.subjects[slot.nums[c]].credit);
+                match first.get(&key) {
+                    Some(f) => {
+                        same[c] = Some(*f);
+                        alternatives[*f].push(c);
+                    },
+                    None => { first.insert(key, c); }
+                }
+            }
+            plan.same.push(same);
+            plan.alternatives.push(alternatives);
+        }
+    }
+
+    /// Explains why the query has no combination. `None` if it has any.
+    pub fn explain(&self, fixsubs: &Vec<(String, usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>, constraints: &Constraints, budget: &Budget)
+     -> Result<Option<Diagnosis>, &str> {
+        let plan = self.plan(fixsubs, reqsubs, selsubs, constraints)?;
+        let mut diagnosis = Diagnosis {
+            fixed_conflicts: Vec::new(),
+            blocked_fixed: Vec::new(),
+            out_of_credit_range: false,
+            unmet_groups: Vec::new(),
+            blocking_subject: None,
+            core: None,
+        };
 
-        let mut sel_no_sub_flag = false;
-        selsubs.sort_unstable_by_key(|x| match self.code_to_subject.get(x) {
-            Some(t) => t.len(),
+        match plan {
+            Some(plan) => {
+                diagnosis.out_of_credit_range = !plan.credits_reachable(0, plan.fix_credits);
+                let n = plan.group_ranges.len();
+                diagnosis.unmet_groups = (0..n).filter(|g| {
+                    let (min, max) = plan.group_ranges[*g];
+                    plan.fix_groups[*g] > max || plan.fix_groups[*g] + plan.rest_groups[*g] < min
+                }).collect();
+                let reachable = plan.reachable();
+                let mut walker = Walker::new(self, plan, budget);
+                if walker.next_with(|_| true).is_some() {
+                    return Ok(None)
+                }
+                // every branch stopped before this subject. unknown if search is stopped by budget.
+                if reachable && !budget.is_truncated() {
+                    diagnosis.blocking_subject = walker.plan.slots.get(walker.deepest).map(|x| x.code.clone());
+                }
+            },
             None => {
-                sel_no_sub_flag = true;
-                0
+                let blocked = self.blocked_mask(constraints)?;
+                let banned = self.banned_classes(constraints)?;
+                let fix: Vec<usize> = fixsubs.iter().map(|x| self.code_to_section[&x.0][x.1]).collect();
+                for (a, num) in fixsubs.iter().zip(fix.iter()) {
+                    if get_bit(&blocked, self.section_bits[*num]) || banned.get(*num).cloned().unwrap_or(false) || self.is_unpaired(&fix, *num) {
+                        diagnosis.blocked_fixed.push(a.clone());
+                    }
+                }
+                for (i, a) in fixsubs.iter().enumerate() {
+                    for b in fixsubs[i+1..].iter() {
+                        let (x, y) = (self.section_bits[self.code_to_section[&a.0][a.1]], self.section_bits[self.code_to_section[&b.0][b.1]]);
+                        let mut mask = vec![BitArray::zero(); self.chunks];
+                        set_bit(&mut mask, y);
+                        if !is_free(self.conflict_row(x), &mask) {
+                            diagnosis.fixed_conflicts.push((a.clone(), b.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        // minimal conflicting set: drop each fixed class or required subject, if rest still has no combination.
+        // selected subjects can always be left out, so they are never in it.
+        // has_combination is false after budget is used up, then the set is wrong.
+        if budget.is_truncated() {
+            return Ok(Some(diagnosis))
+        }
+        let mut fix = fixsubs.clone();
+        let mut req = reqsubs.clone();
+        let mut i = 0;
+        while i < fix.len() {
+            let removed = fix.remove(i);
+            if self.has_combination(&fix, &req, constraints, budget) {
+                fix.insert(i, removed);
+                i += 1;
+            }
+        }
+        let mut i = 0;
+        while i < req.len() {
+            let removed = req.remove(i);
+            if self.has_combination(&fix, &req, constraints, budget) {
+                req.insert(i, removed);
+                i += 1;
             }
-        } );
-        if sel_no_sub_flag {
+        }
+        if !budget.is_truncated() {
+            diagnosis.core = Some(fix.into_iter().map(|x| x.0).chain(req.into_iter()).collect());
+        }
+        Ok(Some(diagnosis))
+    }
+
+    // Search may stop by budget, then this is false.
+    fn has_combination(&self, fixsubs: &Vec<(String, usize)>, reqsubs: &Vec<String>, constraints: &Constraints, budget: &Budget) -> bool {
+        match self.plan(fixsubs, &mut reqsubs.clone(), &mut Vec::new(), constraints) {
+            Ok(Some(plan)) => Walker::new(self, plan, budget).next_with(|_| true).is_some(),
+            _ => false
+        }
+    }
+
+    // Validate query and make search plan. Ok(None) if fixed subjects conflict each other, or break constraints.
+    // reqsubs and selsubs are sorted by number of classes, same as combinate_subjects.
+    fn plan<'a>(&'a self, fixsubs: &Vec<(String, usize)>, reqsubs: &mut Vec<String>, selsubs: &mut Vec<String>, constraints: &Constraints) -> Result<Option<Plan<'a>>, &'a str> {
+        // key of sort is not called for single subject, so check first.
+        if reqsubs.iter().any(|x| !self.code_to_subject.contains_key(x)) {
+            return Err("Invalid required subject");
+        }
+        if selsubs.iter().any(|x| !self.code_to_subject.contains_key(x)) {
             return Err("Invalid selected subject");
         }
+        reqsubs.sort_unstable_by_key(|x| self.code_to_subject.get(x).unwrap().len());
+        selsubs.sort_unstable_by_key(|x| self.code_to_subject.get(x).unwrap().len());
 
-        let mut fix_subs = self.obj_pool.new_rc();
-        let mut fix_mask = BitArray::zero();
-        // in this loop, we findout fixed subjects from code_to_subject, and check are these in conflict.
+        let blocked = self.blocked_mask(constraints)?;
+        let banned = self.banned_classes(constraints)?;
+        let credit_range = constraints.credit_range()?;
+        let day_rule = constraints.day_rule(&self.grid)?;
+        let group_ranges = constraints.group_ranges()?;
+        // groups[code]: bit mask of groups which have the subject.
+        let mut groups: HashMap<&String, u32> = HashMap::new();
+        for (g, group) in constraints.groups.iter().enumerate() {
+            for code in group.subjects.iter() {
+                if !self.code_to_subject.contains_key(code) {return Err("Invalid group subject");}
+                *groups.entry(code).or_insert(0) |= 1 << g;
+            }
+        }
+        let mut fix = Vec::with_capacity(fixsubs.len());
+        let mut fix_mask = vec![BitArray::zero(); self.chunks];
+        let mut fix_times = [0; WEEK_DAYS];
+        let mut fix_ok = true;
         for (sub_code, class_idx) in fixsubs.iter() {
-            let idx: u8 = match self.code_to_subject.get(sub_code) {
-                Some(t) => t[*class_idx as usize],
+            let num: usize = match self.code_to_section.get(sub_code).and_then(|t| t.get(*class_idx)) {
+                Some(t) => *t,
                 None => return Err("Invalid fix subject")
-            } as u8;
-            fix_subs.push(self.code_to_num.get(sub_code).unwrap()[*class_idx]);
-            if fix_mask.get(idx) {return Ok(None)}
-            fix_mask.set(idx, true);
+            };
+            let idx = self.section_bits[num];
+            fix.push(num);
+            for (t, b) in fix_times.iter_mut().zip(self.subjects[num].time_bit.iter()) {
+                *t |= b;
+            }
+            if get_bit(&blocked, idx) || banned.get(num).cloned().unwrap_or(false) || !is_free(self.conflict_row(idx), &fix_mask) {fix_ok = false;}
+            set_bit(&mut fix_mask, idx);
         }
+        // every fixed subject is checked before, so invalid one is always reported.
+        if !fix_ok || !day_rule.check(&fix_times) || fix.iter().any(|x| self.is_unpaired(&fix, *x)) {return Ok(None)}
 
-        let mut sub_comb_list = self.comb_pool.new();
-        sub_comb_list.push(fix_subs);
-        let mut sub_mask_list = self.bit_pool.new();
-        sub_mask_list.push(fix_mask.clone());
-
-        // loop for reqired subjects
-        for req_code in reqsubs.iter() {  // for each subject( = subject code)  
-            let mut is_added = false;
-
-            let mut tmp_req_comb_list = self.comb_pool.new();
-            let mut tmp_req_mask_list = self.bit_pool.new();
-
-            let req_subs = self.code_to_subject.get(req_code).unwrap();
-            let req_subs_idxs = self.code_to_num.get(req_code).unwrap();
-            for (combined_subs, bit) in sub_comb_list.iter().zip(sub_mask_list.iter()) { // for each class with same code
-                for (class_idx, bit_idx) in req_subs.iter().enumerate() { // for each time block in class. subs: Vec<(String, usize)>, bit: BitArray
-                    let sub_conflict_bit: u64x4 =  self.conflict_array[*bit_idx as usize].clone().into();
-                    let combined_bit: u64x4 = bit.clone().into(); // current mask
-                    let m = (sub_conflict_bit | combined_bit).eq(sub_conflict_bit ^ combined_bit).all(); // Check if is it conflict
-                    if m {
-                        is_added = true;
-                        let mut new_sub = combined_subs.clone();
-                        let mut new_bit = bit.clone();
-                        new_sub.push(req_subs_idxs[class_idx]);
-                        new_bit.set(*bit_idx, true);
-                        tmp_req_comb_list.push(new_sub);
-                        tmp_req_mask_list.push(new_bit);
-                    }
-                }
+        let mut fix_groups = vec![0; group_ranges.len()];
+        for (sub_code, _) in fixsubs.iter() {
+            if let Some(mask) = groups.remove(sub_code) {
+                add_groups(&mut fix_groups, mask);
             }
-            sub_comb_list = tmp_req_comb_list;
-            sub_mask_list = tmp_req_mask_list;
+        }
+        // group subjects not in the query are optional, same as selected subjects.
+        let mut extra: Vec<String> = groups.keys()
+            .filter(|x| !reqsubs.contains(x) && !selsubs.contains(x))
+            .map(|x| (*x).clone()).collect();
+        extra.sort_unstable_by_key(|x| (self.code_to_subject.get(x).unwrap().len(), x.clone()));
 
-            if !is_added {
-                return Ok(None)
+        let mut slots = Vec::with_capacity(reqsubs.len() + selsubs.len() + extra.len());
+        for (codes, optional) in [(&*reqsubs, false), (&*selsubs, true), (&extra, true)].iter() {
+            for code in codes.iter() {
+                let (code, bits) = self.code_to_subject.get_key_value(code).unwrap();
+                slots.push(Slot {
+                    code: code,
+                    bits: bits,
+                    nums: self.code_to_num.get(code).unwrap(),
+                    pairs: self.code_to_pair.get(code).unwrap(),
+                    optional: *optional,
+                    groups: groups.get(code).cloned().unwrap_or(0)
+                });
             }
         }
-        
-        for sel_code in selsubs.iter() {
-            let sel_subs = self.code_to_subject.get(sel_code).unwrap();
-            let sel_subs_idxs = self.code_to_num.get(sel_code).unwrap();
-            for idx in 0..sub_comb_list.len() { 
-                for (class_idx, bit_idx) in sel_subs.iter().enumerate() { // subs: Vec<(String, usize)>, bit: BitArray
-                    let combined_subs = &sub_comb_list[idx];
-                    let bit = &sub_mask_list[idx];
-                    let sub_conflict_bit: u64x4 =  self.conflict_array[*bit_idx as usize].clone().into();
-                    let combined_bit: u64x4 = bit.clone().into(); // current mask
-                    let m = (sub_conflict_bit | combined_bit).eq(sub_conflict_bit ^ combined_bit).all();
-                    if m {
-                        let mut new_sub = combined_subs.clone();
-                        let mut new_bit = bit.clone();
-                        new_sub.push(sel_subs_idxs[class_idx]);
-                        new_bit.set(*bit_idx, true);
-                        sub_comb_list.push(new_sub);
-                        sub_mask_list.push(new_bit);
+
+        // rest_groups[d*n + g]: number of slots from d to the end in group g.
+        let n = group_ranges.len();
+        let mut rest_groups = vec![0; (slots.len() + 1) * n];
+        for (d, slot) in slots.iter().enumerate().rev() {
+            for g in 0..n {
+                rest_groups[d * n + g] = rest_groups[(d + 1) * n + g] + ((slot.groups >> g) & 1) as usize;
+            }
+        }
+
+        // least and most credits that slots from d to the end can add.
+        let mut rest = vec![(0, 0); slots.len() + 1];
+        for (d, slot) in slots.iter().enumerate().rev() {
+            let credits = slot.nums.iter().zip(slot.pairs.iter())
+                .map(|(x, p)| self.subjects[*x].credit as u32 + p.map_or(0, |p| self.subjects[p].credit as u32));
+            let min = if slot.optional {0} else {credits.clone().min().unwrap_or(0)};
+            let max = credits.max().unwrap_or(0);
+            rest[d] = (rest[d + 1].0 + min, rest[d + 1].1 + max);
+        }
+        let fix_credits = fix.iter().map(|x| self.subjects[*x].credit as u32).sum();
+
+        Ok(Some(Plan {
+            fix: fix,
+            fix_mask: fix_mask,
+            blocked: blocked,
+            banned: banned,
+            slots: slots,
+            credit_range: credit_range,
+            fix_credits: fix_credits,
+            rest_credits: rest,
+            fix_times: fix_times,
+            day_rule: day_rule,
+            group_ranges: group_ranges,
+            fix_groups: fix_groups,
+            rest_groups: rest_groups,
+            same: Vec::new(),
+            alternatives: Vec::new()
+        }))
+    }
+
+    // Mask of conflict_array idx whose time breaks constraints.
+    fn blocked_mask(&self, constraints: &Constraints) -> Result<Vec<BitArray>, &'static str> {
+        let mut mask = vec![BitArray::zero(); self.chunks];
+        if constraints.is_time_free() {
+            return Ok(mask)
+        }
+        let bits = constraints.blocked_bits(&self.grid)?;
+        for (idx, pattern) in self.patterns.iter().enumerate() {
+            if pattern.iter().zip(bits.iter()).any(|(p, b)| p & b != 0) {
+                set_bit(&mut mask, idx as u32);
+            }
+        }
+        Ok(mask)
+    }
+
+    // Sections excluded by code or professor, by "Subject vector" idx. Empty if constraints have none.
+    fn banned_classes(&self, constraints: &Constraints) -> Result<Vec<bool>, &'static str> {
+        if constraints.exclude.len() == 0 && constraints.avoid_prof.len() == 0 {
+            return Ok(Vec::new())
+        }
+        let mut banned: Vec<bool> = self.subjects.iter().map(|x| constraints.avoids(x)).collect();
+        for (code, class_idx) in constraints.exclude.iter() {
+            match self.code_to_section.get(code).and_then(|t| t.get(*class_idx)) {
+                Some(t) => banned[*t] = true,
+                None => return Err("Invalid exclude subject")
+            }
+        }
+        Ok(banned)
+    }
+
+    // true if fixed class "num" needs a linked class (or a class linking to it) which is not fixed.
+    fn is_unpaired(&self, fix: &[usize], num: usize) -> bool {
+        (self.links[num].len() != 0 && !self.links[num].iter().any(|x| fix.contains(x)))
+            || (self.linked[num] && !fix.iter().any(|x| self.links[*x].contains(&num)))
+    }
+
+    pub fn subjects(&self) -> &Vec<Subject> {
+        &self.subjects
+    }
+
+    pub fn grid(&self) -> &TimeGrid {
+        &self.grid
+    }
+
+    /// Score of single combination (indices of "subjects").
+    pub fn score(&self, comb: &[usize], scorer: &dyn Scorer) -> f64 {
+        let table: Vec<&Subject> = comb.iter().map(|x| &self.subjects[*x]).collect();
+        scorer.score(&table)
+    }
+
+    /// Sort combinations from best to worst. Combinations with same score keep their order.
+    pub fn rank_combinations<T: AsRef<[usize]>>(&self, combs: &mut Vec<T>, scorer: &dyn Scorer) {
+        let mut scored: Vec<(f64, T)> = combs.drain(..).map(|x| (self.score(x.as_ref(), scorer), x)).collect();
+        scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        combs.extend(scored.into_iter().map(|(_, x)| x));
+    }
+}
+
+/// Result of `SubjectCombinator::explain`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnosis {
+    /// Pairs of fixed classes, (code, class idx), whose times overlap.
+    pub fixed_conflicts: Vec<((String, usize), (String, usize))>,
+    /// Fixed classes, (code, class idx), which break constraints, are excluded, or miss their linked class.
+    pub blocked_fixed: Vec<(String, usize)>,
+    /// No choice of classes fits the credit range, even if times did not matter.
+    pub out_of_credit_range: bool,
+    /// Elective groups (index of `Constraints::groups`) which cannot be met, even if times did not matter.
+    pub unmet_groups: Vec<usize>,
+    /// Required subject which has no class fitting with earlier choices, in search order.
+    /// `None` also if the search is stopped by budget.
+    pub blocking_subject: Option<String>,
+    /// Minimal set of fixed and required subject codes which cannot be taken together.
+    /// Taking out any one of them makes a combination possible.
+    /// `None` if budget is used up before it is found.
+    pub core: Option<Vec<String>>,
+}
+
+/// Iterator over combinations of one query. See `SubjectCombinator::iter_combinations`.
+pub struct Combinations<'a> {
+    // None if fixed subjects conflict each other.
+    walker: Option<Walker<'a>>,
+}
+
+impl<'a> Iterator for Combinations<'a> {
+    type Item = SingleCombination;
+
+    fn next(&mut self) -> Option<SingleCombination> {
+        self.walker.as_mut()?.next_with(|_| true).map(|x| x.to_vec())
+    }
+}
+
+// Search plan of one query.
+// Fixed classes are always in, then one class is chosen for each slot (or none, if optional).
+#[derive(Clone)]
+struct Plan<'a> {
+    fix: Vec<usize>,
+    fix_mask: Vec<BitArray>,
+    // conflict_array idx which cannot be taken.
+    blocked: Vec<BitArray>,
+    // banned[num]: "Subject vector" idx which cannot be taken. empty if none.
+    banned: Vec<bool>,
+    slots: Vec<Slot<'a>>,
+    // (min, max) credits of combination.
+    credit_range: (u32, u32),
+    fix_credits: u32,
+    // rest_credits[d]: (least, most) credits that slots d.. can add.
+    rest_credits: Vec<(u32, u32)>,
+    // time bits of fixed classes.
+    fix_times: [u64; WEEK_DAYS],
+    day_rule: DayRule,
+    // (min, max) subjects of each group.
+    group_ranges: Vec<(usize, usize)>,
+    // subjects of each group in fixed classes.
+    fix_groups: Vec<usize>,
+    // rest_groups[d*n + g]: slots from d to the end in group g.
+    rest_groups: Vec<usize>,
+    // same[d][c]: earlier choice of slot d which choice c is same as. empty if not grouped.
+    same: Vec<Vec<Option<usize>>>,
+    // alternatives[d][c]: choices of slot d same as choice c, c included.
+    alternatives: Vec<Vec<Vec<usize>>>,
+}
+
+impl<'a> Plan<'a> {
+    // true if some choice of slots from "depth" can bring "credits" into the range.
+    fn credits_reachable(&self, depth: usize, credits: u32) -> bool {
+        let (min, max) = self.rest_credits[depth];
+        credits + min <= self.credit_range.1 && credits + max >= self.credit_range.0
+    }
+
+    // true if slots from "depth" can still bring every group into its range.
+    fn groups_reachable(&self, depth: usize, counts: &[usize]) -> bool {
+        let n = self.group_ranges.len();
+        self.group_ranges.iter().zip(counts.iter()).enumerate().all(|(g, ((min, max), count))| {
+            count <= max && count + self.rest_groups[depth * n + g] >= *min
+        })
+    }
+
+    // true if fixed classes and slots can keep every constraint, ignoring time.
+    fn reachable(&self) -> bool {
+        self.credits_reachable(0, self.fix_credits) && self.groups_reachable(0, &self.fix_groups)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Slot<'a> {
+    code: &'a String,
+    // conflict_array idx of each class.
+    bits: &'a Vec<u32>,
+    // "Subject vector" idx of each class.
+    nums: &'a Vec<usize>,
+    // "Subject vector" idx of linked class taken with each class.
+    pairs: &'a Vec<Option<usize>>,
+    // selected subject can be left out.
+    optional: bool,
+    // bit mask of elective groups which have this subject.
+    groups: u32,
+}
+
+// Depth-first walk of a plan, using explicit stack so it can stop and resume at every leaf.
+// Choice at each slot: class 0..n, then "no class" if the slot is optional.
+struct Walker<'a> {
+    combinator: &'a SubjectCombinator,
+    plan: Plan<'a>,
+    // current (partial) combination.
+    comb: Vec<usize>,
+    // credits of "comb".
+    credits: u32,
+    // subjects of each group in "comb".
+    group_counts: Vec<usize>,
+    // time bits after deciding d slots: times[d]. only kept if plan has day rule.
+    times: Vec<[u64; WEEK_DAYS]>,
+    // mask after deciding d slots: masks[d*chunks..(d+1)*chunks].
+    masks: Vec<BitArray>,
+    // choices[d]: choice made at slot d.
+    choices: Vec<usize>,
+    // next choice to try at slot choices.len().
+    next: usize,
+    at_leaf: bool,
+    // deepest number of decided slots so far.
+    deepest: usize,
+    done: bool,
+    // only this choice is walked at the first slot, if set.
+    first: Option<usize>,
+    budget: &'a Budget,
+    // nodes expanded, but not reported to budget yet.
+    spent: u64,
+}
+
+impl<'a> Walker<'a> {
+    fn new(combinator: &'a SubjectCombinator, plan: Plan<'a>, budget: &'a Budget) -> Self {
+        Walker {
+            combinator: combinator,
+            comb: plan.fix.clone(),
+            credits: plan.fix_credits,
+            group_counts: plan.fix_groups.clone(),
+            times: vec![plan.fix_times],
+            masks: plan.fix_mask.clone(),
+            choices: Vec::with_capacity(plan.slots.len()),
+            done: !plan.reachable(),
+            plan: plan,
+            next: 0,
+            at_leaf: false,
+            deepest: 0,
+            first: None,
+            budget: budget,
+            spent: 0,
+        }
+    }
+
+    // Walker of the branch which takes "first" choice at the first slot.
+    fn branch(combinator: &'a SubjectCombinator, plan: Plan<'a>, budget: &'a Budget, first: usize) -> Self {
+        let mut walker = Walker::new(combinator, plan, budget);
+        walker.next = first;
+        walker.first = Some(first);
+        walker
+    }
+
+    // Returns next complete combination.
+    // `accept` is called with each new partial combination; returning false skips that branch.
+    fn next_with<F: FnMut(&[usize]) -> bool>(&mut self, mut accept: F) -> Option<&[usize]> {
+        loop {
+            if self.done {return None;}
+            self.spent += 1;
+            if self.spent == BUDGET_BATCH {
+                self.spent = 0;
+                if !self.budget.spend(BUDGET_BATCH) {
+                    self.done = true;
+                    return None;
+                }
+            }
+            let depth = self.choices.len();
+            if depth == self.plan.slots.len() {
+                if !self.at_leaf {
+                    self.at_leaf = true;
+                    return Some(&self.comb);
+                }
+                self.at_leaf = false;
+                self.backtrack();
+                continue;
+            }
+
+            let slot = self.plan.slots[depth];
+            let choice = self.next;
+            if depth == 0 && self.first.map_or(false, |f| choice != f) {
+                self.done = true;
+                continue;
+            }
+            let chunks = self.combinator.chunks;
+            let mask = depth * chunks..(depth + 1) * chunks;
+            if choice < slot.bits.len() {
+                if self.plan.same.len() != 0 && self.plan.same[depth][choice].is_some() {
+                    self.next += 1;
+                    continue;
+                }
+                let bit_idx = slot.bits[choice];
+                let pair = slot.pairs[choice];
+                if get_bit(&self.plan.blocked, bit_idx)
+                    || (self.plan.banned.len() != 0 && (self.plan.banned[slot.nums[choice]] || pair.map_or(false, |p| self.plan.banned[p]))) || !is_free(self.combinator.conflict_row(bit_idx), &self.masks[mask.clone()]) {
+                    self.next += 1;
+                    continue;
+                }
+                for i in mask {
+                    let b = self.masks[i];
+                    self.masks.push(b);
+                }
+                set_bit(&mut self.masks[(depth + 1) * chunks..], bit_idx);
+                add_groups(&mut self.group_counts, slot.groups);
+                let mut t = self.times.get(depth).cloned().unwrap_or_default();
+                for num in std::iter::once(slot.nums[choice]).chain(pair) {
+                    self.comb.push(num);
+                    self.credits += self.combinator.subjects[num].credit as u32;
+                    if !self.plan.day_rule.is_empty() {
+                        for (t, b) in t.iter_mut().zip(self.combinator.subjects[num].time_bit.iter()) {
+                            *t |= b;
+                        }
                     }
                 }
+                if !self.plan.day_rule.is_empty() {
+                    self.times.push(t);
+                }
+            }
+            else if slot.optional && choice == slot.bits.len() {
+                for i in mask {
+                    let b = self.masks[i];
+                    self.masks.push(b);
+                }
+                if !self.plan.day_rule.is_empty() {
+                    self.times.push(self.times[depth]);
+                }
+            }
+            else {
+                self.backtrack();
+                continue;
+            }
+            self.choices.push(choice);
+            self.deepest = self.deepest.max(self.choices.len());
+            self.next = 0;
+            if !self.plan.credits_reachable(depth + 1, self.credits)
+                || !self.plan.groups_reachable(depth + 1, &self.group_counts)
+                || (!self.plan.day_rule.is_empty() && !self.plan.day_rule.check(&self.times[depth + 1]))
+                || !accept(&self.comb) {
+                self.backtrack();
             }
         }
+    }
 
-        if sub_comb_list.len() == 0 {
-            Ok(None)
+    // Current combination with interchangeable classes. (see `SubjectCombinator::combinate_grouped`)
+    fn grouped(&self) -> GroupedCombination {
+        let mut grouped: GroupedCombination = self.plan.fix.iter().map(|x| vec![*x]).collect();
+        for (d, choice) in self.choices.iter().enumerate() {
+            let slot = self.plan.slots[d];
+            if *choice >= slot.bits.len() {continue;}
+            let alternatives = self.plan.alternatives.get(d).map_or(vec![*choice], |x| x[*choice].clone());
+            grouped.push(alternatives.into_iter().map(|x| slot.nums[x]).collect());
+            if let Some(p) = slot.pairs[*choice] {
+                grouped.push(vec![p]);
+            }
         }
-        else {
-            sub_comb_list.reverse();
-            Ok(Some(
-                sub_comb_list
-                // sub_comb_list.into_iter().map(
-                //     |x| x.detach()
-                // ).collect()
-            ))
+        grouped
+    }
+
+    // Undo last choice, and move to the choice after it.
+    fn backtrack(&mut self) {
+        match self.choices.pop() {
+            Some(choice) => {
+                let len = self.masks.len() - self.combinator.chunks;
+                self.masks.truncate(len);
+                if !self.plan.day_rule.is_empty() {
+                    self.times.pop();
+                }
+                let slot = self.plan.slots[self.choices.len()];
+                if choice < slot.bits.len() {
+                    let taken = if slot.pairs[choice].is_some() {2} else {1};
+                    for _ in 0..taken {
+                        let num = self.comb.pop().unwrap();
+                        self.credits -= self.combinator.subjects[num].credit as u32;
+                    }
+                    for g in 0..self.group_counts.len() {
+                        self.group_counts[g] -= ((slot.groups >> g) & 1) as usize;
+                    }
+                }
+                self.next = choice + 1;
+            },
+            None => self.done = true
         }
     }
 }
 
+// Combination with score, ordered worst first so BinaryHeap keeps the worst on top.
+struct Ranked {
+    score: f64,
+    seq: usize,
+    comb: SingleCombination,
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.partial_cmp(&self.score).unwrap_or(Ordering::Equal)
+            .then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
 unsafe impl Send for SubjectCombinator {}
 unsafe impl Sync for SubjectCombinator {}
 
-fn is_conflict(a: &[u64; 5], b: &[u64; 5]) -> bool
+// true if class of "conflict" row can be added to "mask".
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn is_free(conflict: &[BitArray], mask: &[BitArray]) -> bool {
+    conflict.iter().zip(mask.iter()).all(|(c, m)| {
+        let sub_conflict_bit: u64x4 = c.clone().into();
+        let combined_bit: u64x4 = m.clone().into();
+        (sub_conflict_bit | combined_bit).eq(sub_conflict_bit ^ combined_bit).all()
+    })
+}
+
+// Portable version of above, for stable Rust.
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+fn is_free(conflict: &[BitArray], mask: &[BitArray]) -> bool {
+    conflict.iter().zip(mask.iter()).all(|(c, m)| {
+        c.elem.iter().zip(m.elem.iter()).all(|(x, y)| x & y == 0)
+    })
+}
+
+#[inline(always)]
+fn set_bit(row: &mut [BitArray], idx: u32) {
+    row[(idx / 256) as usize].set((idx % 256) as u8, true);
+}
+
+// Count one subject in each group of "mask".
+fn add_groups(counts: &mut [usize], mask: u32) {
+    for (g, count) in counts.iter_mut().enumerate() {
+        *count += ((mask >> g) & 1) as usize;
+    }
+}
+
+#[inline(always)]
+fn get_bit(row: &[BitArray], idx: u32) -> bool {
+    row[(idx / 256) as usize].get((idx % 256) as u8)
+}
+
+fn is_conflict(a: &[u64; WEEK_DAYS], b: &[u64; WEEK_DAYS]) -> bool
 {
-    let mut tmp: [u64; 5] = [0,0,0,0,0];
+    let mut tmp: [u64; WEEK_DAYS] = [0; WEEK_DAYS];
     let mut k = false;
-    for i in 0..5 as usize
+    for i in 0..WEEK_DAYS
     {
         if a[i] | b[i] != a[i] ^ b[i]//conflict occur
         {
@@ -287,7 +1145,7 @@ fn is_conflict(a: &[u64; 5], b: &[u64; 5]) -> bool
     k
 }
 
-fn hamming_weight(x: &[u64; 5]) -> u32
+fn hamming_weight(x: &[u64; WEEK_DAYS]) -> u32
 {
     const M1:  u64  = 0x5555555555555555;
     const M2:  u64  = 0x3333333333333333;
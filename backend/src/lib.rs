@@ -3,4 +3,8 @@ pub mod Subject;
 #[allow(non_snake_case)]
 pub mod Tools;
 #[allow(non_snake_case)]
-pub mod DB;
\ No newline at end of file
+pub mod DB;
+#[allow(non_snake_case)]
+pub mod Score;
+#[allow(non_snake_case)]
+pub mod Constraint;
\ No newline at end of file
@@ -1,6 +1,44 @@
 #[cfg(test)]
 mod tests {
     use backend::*;
+    use backend::DB::ShareStore;
+
+    fn sample_subjects() -> Vec<Subject::Subject> {
+        let rows = vec![
+            ("SE101", 1, 3, "월09:00-10:30(E7-101)수09:00-10:30(E7-101)"),
+            ("SE101", 2, 3, "화13:00-14:30(E7-101)목13:00-14:30(E7-101)"),
+            ("SE102", 1, 2, "월10:30-12:00(E7-102)"),
+            ("SE102", 2, 2, "금15:00-16:30(E7-102)"),
+            ("HL201", 1, 3, "화09:00-10:30(E6-201)"),
+            ("HL201", 2, 3, "월09:00-10:30(E6-201)"),
+        ];
+        rows.into_iter().enumerate().map(|(i, (code, class_num, credit, time))| {
+            Subject::Subject::new(i as u32 + 1, code.to_string(), class_num, "과목".to_string(), "교수".to_string(), credit, time.to_string())
+        }).collect()
+    }
+
+    fn sorted(comb: &Vec<usize>) -> Vec<usize> {
+        let mut v = comb.clone();
+        v.sort();
+        v
+    }
+
+    fn codes(v: &[&str]) -> Vec<String> {
+        v.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn none() -> Constraint::Constraints {
+        Constraint::Constraints::default()
+    }
+
+    fn unlimited() -> Tools::Budget {
+        Tools::Budget::unlimited()
+    }
+
+    // number of combinations of required subjects `req`.
+    fn count(combinator: &Tools::SubjectCombinator, req: &[&str], constraints: &Constraint::Constraints) -> usize {
+        combinator.count_combinations(&vec![], &mut codes(req), &mut vec![], constraints, &unlimited()).unwrap()
+    }
     #[test]
     fn test_bitarray() {
         let mut a = Tools::BitArray::zero();
@@ -37,8 +75,682 @@ mod tests {
         let fix_subs = vec![("SE324a".to_string(), 0), ("SE334a".to_string(), 0), ("SE380".to_string(), 0), ("HL303".to_string(), 31)];
         let mut req_subs = vec!["HL203".to_string(), "HL204".to_string(), "HL305".to_string()];
         let mut sel_subs = vec!["HL320".to_string()];
-        let ans = combinator.combinate_subjects(&fix_subs, &mut req_subs, &mut sel_subs);
+        let ans = combinator.combinate_subjects(&fix_subs, &mut req_subs, &mut sel_subs, &none(), &unlimited());
 
         assert_eq!(ans.unwrap().unwrap().len(), 12);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_scorers() {
+        use Score::Scorer;
+        let subs = sample_subjects();
+        let packed = vec![&subs[0], &subs[2]];
+        let spread = vec![&subs[1], &subs[3]];
+        assert_eq!(Score::FewestDays.score(&packed), -2.0);
+        assert_eq!(Score::FewestDays.score(&spread), -3.0);
+        assert_eq!(Score::LeastGap.score(&packed), 0.0);
+        assert_eq!(Score::LeastGap.score(&vec![&subs[0], &subs[4], &subs[1]]), -2.5);
+        assert_eq!(Score::LatestStart.score(&spread), 13.0);
+        assert_eq!(Score::EarliestFinish.score(&packed), -12.0);
+        assert_eq!(Score::TotalCredits.score(&packed), 5.0);
+
+        let weighted = Score::Weighted::new()
+            .add(1.0, Box::new(Score::FewestDays))
+            .add(0.5, Box::new(Score::TotalCredits));
+        assert_eq!(weighted.score(&packed), 0.5);
+        let mut names = std::collections::HashMap::new();
+        names.insert("unknown".to_string(), 1.0);
+        assert!(Score::Weighted::from_names(names.iter()).is_err());
+    }
+
+    #[test]
+    fn test_rank_combinations() {
+        let combinator = Tools::SubjectCombinator::new(sample_subjects());
+        let mut req_subs = vec!["SE101".to_string(), "SE102".to_string()];
+        let ans = combinator.combinate_subjects(&vec![], &mut req_subs, &mut vec![], &none(), &unlimited()).unwrap().unwrap();
+        let mut comb: Vec<&Vec<usize>> = ans.iter().map(|x| x.as_ref()).collect();
+        assert_eq!(comb.len(), 4);
+
+        combinator.rank_combinations(&mut comb, &Score::FewestDays);
+        assert_eq!(sorted(comb[0]), vec![0, 2]);
+        combinator.rank_combinations(&mut comb, &Score::LatestStart);
+        assert_eq!(sorted(comb[0]), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_top_k() {
+        let combinator = Tools::SubjectCombinator::new(sample_subjects());
+        let mut req_subs = vec!["SE101".to_string(), "SE102".to_string()];
+        let mut sel_subs = vec!["HL201".to_string()];
+        let all = combinator.combinate_subjects(&vec![], &mut req_subs, &mut sel_subs, &none(), &unlimited()).unwrap().unwrap();
+        let mut all: Vec<Vec<usize>> = all.iter().map(|x| sorted(x.as_ref())).collect();
+        all.sort();
+
+        // large k returns every combination.
+        let top = combinator.combinate_top_k(&vec![], &mut req_subs, &mut sel_subs, &none(), &unlimited(), 100, &Score::TotalCredits).unwrap().unwrap();
+        let mut top: Vec<Vec<usize>> = top.iter().map(sorted).collect();
+        top.sort();
+        assert_eq!(top, all);
+
+        // with pruning, best ones are same as full ranking.
+        let mut ranked = all.clone();
+        combinator.rank_combinations(&mut ranked, &Score::FewestDays);
+        let top = combinator.combinate_top_k(&vec![], &mut req_subs, &mut sel_subs, &none(), &unlimited(), 3, &Score::FewestDays).unwrap().unwrap();
+        assert_eq!(top.len(), 3);
+        for (a, b) in top.iter().zip(ranked.iter()) {
+            assert_eq!(combinator.score(a, &Score::FewestDays), combinator.score(b, &Score::FewestDays));
+        }
+        assert_eq!(sorted(&top[0]), vec![0, 2]);
+
+        let fix_subs = vec![("SE101".to_string(), 0), ("HL201".to_string(), 1)];
+        assert!(combinator.combinate_top_k(&fix_subs, &mut vec![], &mut vec![], &none(), &unlimited(), 3, &Score::FewestDays).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_iter_combinations() {
+        let combinator = Tools::SubjectCombinator::new(sample_subjects());
+        let mut req_subs = vec!["SE101".to_string(), "SE102".to_string()];
+        let mut sel_subs = vec!["HL201".to_string()];
+        let all = combinator.combinate_subjects(&vec![], &mut req_subs, &mut sel_subs, &none(), &unlimited()).unwrap().unwrap();
+        let mut all: Vec<Vec<usize>> = all.iter().map(|x| sorted(x.as_ref())).collect();
+        all.sort();
+
+        let mut iterated: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut req_subs, &mut sel_subs, &none(), &unlimited()).unwrap()
+            .map(|x| sorted(&x)).collect();
+        iterated.sort();
+        assert_eq!(iterated, all);
+
+        assert_eq!(combinator.count_combinations(&vec![], &mut req_subs, &mut sel_subs, &none(), &unlimited()).unwrap(), all.len());
+
+        let first_two: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut req_subs, &mut sel_subs, &none(), &unlimited()).unwrap().take(2).collect();
+        assert_eq!(first_two.len(), 2);
+
+        let fix_subs = vec![("SE101".to_string(), 0), ("HL201".to_string(), 1)];
+        assert_eq!(combinator.iter_combinations(&fix_subs, &mut vec![], &mut vec![], &none(), &unlimited()).unwrap().count(), 0);
+        assert_eq!(combinator.count_combinations(&fix_subs, &mut vec![], &mut vec![], &none(), &unlimited()).unwrap(), 0);
+        assert!(combinator.iter_combinations(&vec![], &mut vec!["XX000".to_string()], &mut vec![], &none(), &unlimited()).is_err());
+    }
+
+    #[test]
+    fn test_many_time_patterns() {
+        // 5 days * 16 start times * 4 lengths = 320 distinct time patterns.
+        const DAYS: [&str; 5] = ["월", "화", "수", "목", "금"];
+        let mut subs = Vec::new();
+        for day in DAYS.iter() {
+            for start in 0..16 {
+                for len in 1..5 {
+                    let (s, e) = (9*60 + start*30, 9*60 + (start+len)*30);
+                    let time = format!("{}{:02}:{:02}-{:02}:{:02}(E7-101)", day, s/60, s%60, e/60, e%60);
+                    let n = subs.len() as u32 + 1;
+                    subs.push(Subject::Subject::new(n, format!("T{}", n), 1, "과목".to_string(), "교수".to_string(), 3, time));
+                }
+            }
+        }
+        let combinator = Tools::SubjectCombinator::new(subs);
+        // T301: 금 09:00-09:30, T302: 금 09:00-10:00, T305: 금 09:30-10:00
+        assert_eq!(count(&combinator, &["T301", "T302"], &none()), 0);
+        assert_eq!(count(&combinator, &["T301", "T305"], &none()), 1);
+        // T1: 월 09:00-09:30, across first and second chunk of conflict bits.
+        assert_eq!(count(&combinator, &["T1", "T301", "T305"], &none()), 1);
+        assert_eq!(count(&combinator, &["T1", "T2", "T305"], &none()), 0);
+    }
+
+    #[test]
+    fn test_weekend() {
+        let sat = Subject::Subject::new(1, "SE201".to_string(), 1, "실험".to_string(), "교수".to_string(), 1, "토10:00-13:00(E7-L01)".to_string());
+        assert_eq!(sat.time_tuple[5], vec![(600, 780)]);
+        assert_ne!(sat.time_bit[5], 0);
+        assert!(sat.to_array(0).contains("\"토\":[[600,780]]"));
+
+        let subs = vec![
+            sat,
+            Subject::Subject::new(2, "SE202".to_string(), 1, "실험".to_string(), "교수".to_string(), 1, "토12:00-14:00(E7-L02)".to_string()),
+            Subject::Subject::new(3, "SE203".to_string(), 1, "실험".to_string(), "교수".to_string(), 1, "일12:00-14:00(E7-L02)".to_string()),
+        ];
+        let combinator = Tools::SubjectCombinator::new(subs);
+        let mut req = vec!["SE201".to_string(), "SE202".to_string()];
+        assert_eq!(combinator.count_combinations(&vec![], &mut req, &mut vec![], &none(), &unlimited()).unwrap(), 0);
+        let mut req = vec!["SE201".to_string(), "SE203".to_string()];
+        assert_eq!(combinator.count_combinations(&vec![], &mut req, &mut vec![], &none(), &unlimited()).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_load_five_days() {
+        // data.json saved before weekend support.
+        let json = r#"[{"number":1,"code":"SE101","class_num":1,"class_name":"과목","prof":"교수","credit":3,
+            "time_place":"월09:00-10:30(E7-101)","place":["E7-101"],
+            "time_tuple":[[[540,630]],[],[],[],[]],"time_bit":[7,0,0,0,0]}]"#;
+        let subs: Vec<Subject::Subject> = serde_json::from_str(json).unwrap();
+        assert_eq!(subs[0].time_bit, [7, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(subs[0].time_tuple[5].len(), 0);
+    }
+
+    #[test]
+    fn test_time_grid() {
+        let grid = Subject::TimeGrid::default();
+        assert_eq!(grid.bits(&(8*60+30, 9*60)).unwrap(), 0b1100);
+        assert_eq!(grid.bits(&(8*60+10, 8*60+20)).unwrap(), 0b11);
+        assert!(grid.bits(&(7*60, 9*60)).is_err());
+        assert!(grid.bits(&(21*60, 23*60)).is_err());
+        assert!(Subject::TimeGrid::new(9, 8, 30).is_err());
+        assert!(Subject::TimeGrid::new(0, 24, 15).is_err());
+        assert!(Subject::TimeGrid::new(0, 24, 30).is_ok());
+
+        let new = |n: u32, time: &str| Subject::Subject::new(n, format!("SE{}", n), 1, "과목".to_string(), "교수".to_string(), 3, time.to_string());
+        let subs = vec![
+            new(1, "월08:30-10:00(E7-L01)"),
+            new(2, "월10:00-10:45(E7-101)"),
+            new(3, "월10:45-12:00(E7-101)"),
+            new(4, "월10:40-12:00(E7-101)"),
+            new(5, "월19:00-21:45(E7-101)"),
+        ];
+        let combinator = Tools::SubjectCombinator::new(subs);
+        assert_eq!(count(&combinator, &["SE1", "SE2", "SE3", "SE5"], &none()), 1);
+        assert_eq!(count(&combinator, &["SE2", "SE4"], &none()), 0);
+
+        // coarse grid of evening only.
+        let evening = Subject::TimeGrid::new(18, 24, 60).unwrap();
+        let sub = Subject::Subject::with_grid(1, "SE1".to_string(), 1, "과목".to_string(), "교수".to_string(), 3, "화18:30-20:00(E7-101)".to_string(), &evening);
+        assert_eq!(sub.time_bit[1], 0b11);
+    }
+
+    #[test]
+    fn test_subject_parse_error() {
+        use Subject::SubjectParseError::*;
+        let parse = |time: &str| Subject::Subject::try_new(1, "SE101".to_string(), 1, "과목".to_string(), "교수".to_string(), 3, time.to_string()).map(|_| ());
+        assert_eq!(parse("월09:00-10:30(E7-101)"), Ok(()));
+        assert_eq!(parse(""), Ok(()));
+        assert_eq!(parse("월09:70-10:30(E7-101)"), Err(BadTimeFormat("09:70".to_string())));
+        assert_eq!(parse("월10:30-09:00(E7-101)"), Err(BadTimeFormat("10:30-09:00".to_string())));
+        assert!(matches!(parse("월06:00-07:00(E7-101)"), Err(OutOfRange(_))));
+        assert_eq!(parse("월09:00-10:30(E7-101)월10:00-11:00(E7-101)"), Err(OverlappingSlots("월10:00-11:00(E7-101)".to_string())));
+        // back-to-back times are not overlapping.
+        assert_eq!(parse("월09:00-10:10(E7-101)월10:10-11:00(E7-101)"), Ok(()));
+        // whole text should be times.
+        assert_eq!(parse("월9:00-10:30(E7-101)"), Err(BadTimeFormat("월9:00-10:30(E7-101)".to_string())));
+        assert_eq!(parse("월09:00-10:30(E7-101)/화"), Err(BadTimeFormat("/화".to_string())));
+        assert_eq!(parse("월09:00-10:30(E7-101), 수09:00-10:30(E7-101)"), Ok(()));
+        assert_eq!(parse("Mo09:00-10:30(E7-101)"), Err(UnknownDay("Mo".to_string())));
+
+        // No of /api/data is position in the vector, even after a row is skipped.
+        let subs = vec![
+            Subject::Subject::new(1, "SE101".to_string(), 1, "과목".to_string(), "교수".to_string(), 3, "월09:00-10:30(E7-101)".to_string()),
+            Subject::Subject::new(3, "SE102".to_string(), 1, "과목".to_string(), "교수".to_string(), 3, "화09:00-10:30(E7-101)".to_string()),
+        ];
+        assert!(Subject::Subject::zipped_json(&subs).contains("[1,\"SE102\""));
+    }
+
+    #[test]
+    fn test_explain() {
+        let new = |n: u32, code: &str, class_num: u8, time: &str| Subject::Subject::new(n, code.to_string(), class_num, "과목".to_string(), "교수".to_string(), 3, time.to_string());
+        let combinator = Tools::SubjectCombinator::new(vec![
+            new(1, "A", 1, "월09:00-10:30(E7-101)"),
+            new(2, "B", 1, "월10:00-11:00(E7-101)"),
+            new(3, "C", 1, "화09:00-10:30(E7-101)"),
+            new(4, "C", 2, "월09:30-10:00(E7-101)"),
+            new(5, "D", 1, "수09:00-10:30(E7-101)"),
+        ]);
+
+        assert_eq!(combinator.explain(&vec![], &mut codes(&["A", "C"]), &mut vec![], &none(), &unlimited()).unwrap(), None);
+
+        let fix_subs = vec![("A".to_string(), 0), ("B".to_string(), 0), ("D".to_string(), 0)];
+        let diagnosis = combinator.explain(&fix_subs, &mut vec![], &mut vec![], &none(), &unlimited()).unwrap().unwrap();
+        assert_eq!(diagnosis.fixed_conflicts, vec![(("A".to_string(), 0), ("B".to_string(), 0))]);
+        assert_eq!(diagnosis.blocking_subject, None);
+        assert_eq!(diagnosis.core, Some(codes(&["A", "B"])));
+
+        let diagnosis = combinator.explain(&vec![], &mut codes(&["C", "D", "A", "B"]), &mut codes(&["C"]), &none(), &unlimited()).unwrap().unwrap();
+        assert_eq!(diagnosis.fixed_conflicts.len(), 0);
+        assert!(diagnosis.blocking_subject == Some("A".to_string()) || diagnosis.blocking_subject == Some("B".to_string()));
+        let mut core = diagnosis.core.clone().unwrap();
+        core.sort();
+        assert_eq!(core, codes(&["A", "B"]));
+
+        // unknown after budget is used up
+        let budget = Tools::Budget::unlimited();
+        budget.stop();
+        let diagnosis = combinator.explain(&vec![], &mut codes(&["C", "D", "A", "B"]), &mut vec![], &none(), &budget).unwrap().unwrap();
+        assert_eq!((diagnosis.blocking_subject, diagnosis.core), (None, None));
+    }
+
+    #[test]
+    fn test_constraints() {
+        use Constraint::Constraints;
+        let combinator = Tools::SubjectCombinator::new(sample_subjects());
+
+        let free_monday = Constraints { free_days: vec![0], ..Default::default() };
+        let all: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut codes(&["SE101", "SE102", "HL201"]), &mut vec![], &free_monday, &unlimited()).unwrap().collect();
+        assert_eq!(all.iter().map(sorted).collect::<Vec<_>>(), vec![vec![1, 3, 4]]);
+
+        let late = Constraints { no_class_before: Some(10 * 60), ..Default::default() };
+        assert_eq!(count(&combinator, &["HL201"], &late), 0);
+        assert_eq!(count(&combinator, &["SE101"], &late), 1);
+        let early = Constraints { no_class_after: Some(15 * 60), ..Default::default() };
+        assert_eq!(count(&combinator, &["SE102"], &early), 1);
+        let blocked = Constraints { blocked_slots: vec![(1, 13 * 60, 14 * 60)], ..Default::default() };
+        assert_eq!(count(&combinator, &["SE101"], &blocked), 1);
+
+        // fixed class on a free day.
+        let free_wednesday = Constraints { free_days: vec![2], ..Default::default() };
+        let fix_subs = vec![("SE101".to_string(), 0)];
+        assert_eq!(combinator.count_combinations(&fix_subs, &mut vec![], &mut vec![], &free_wednesday, &unlimited()).unwrap(), 0);
+        let diagnosis = combinator.explain(&fix_subs, &mut vec![], &mut vec![], &free_wednesday, &unlimited()).unwrap().unwrap();
+        assert_eq!(diagnosis.blocked_fixed, fix_subs);
+        assert_eq!(diagnosis.core, Some(codes(&["SE101"])));
+
+        let invalid = Constraints { free_days: vec![7], ..Default::default() };
+        assert!(combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut vec![], &invalid, &unlimited()).is_err());
+    }
+
+    #[test]
+    fn test_credit_range() {
+        use Constraint::Constraints;
+        let combinator = Tools::SubjectCombinator::new(sample_subjects());
+        let credits = |comb: &Vec<usize>| comb.iter().map(|x| combinator.subjects()[*x].credit as u32).sum::<u32>();
+
+        // SE101(3) required, SE102(2) and HL201(3) selected.
+        let range = Constraints { min_credits: Some(5), max_credits: Some(6), ..Default::default() };
+        let all: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut codes(&["SE101"]), &mut codes(&["SE102", "HL201"]), &range, &unlimited()).unwrap().collect();
+        let any_credits = combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut codes(&["SE102", "HL201"]), &none(), &unlimited()).unwrap();
+        assert!(all.len() > 0 && all.len() < any_credits);
+        assert!(all.iter().all(|x| (5..=6).contains(&credits(x))));
+
+        let top = combinator.combinate_top_k(&vec![], &mut codes(&["SE101"]), &mut codes(&["SE102", "HL201"]), &range, &unlimited(), 10, &Score::TotalCredits).unwrap().unwrap();
+        assert_eq!(top.len(), all.len());
+
+        let too_many = Constraints { min_credits: Some(9), ..Default::default() };
+        assert_eq!(combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut codes(&["SE102"]), &too_many, &unlimited()).unwrap(), 0);
+        let diagnosis = combinator.explain(&vec![], &mut codes(&["SE101"]), &mut codes(&["SE102"]), &too_many, &unlimited()).unwrap().unwrap();
+        assert!(diagnosis.out_of_credit_range);
+        assert_eq!(diagnosis.blocking_subject, None);
+
+        let invalid = Constraints { min_credits: Some(10), max_credits: Some(9), ..Default::default() };
+        assert!(combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut vec![], &invalid, &unlimited()).is_err());
+    }
+
+    #[test]
+    fn test_day_rules() {
+        use Constraint::Constraints;
+        let combinator = Tools::SubjectCombinator::new(sample_subjects());
+
+        // SE101 class 1 and SE102 class 1 are 월09:00-12:00 in a row.
+        let two_hours = Constraints { max_consecutive: Some(120), ..Default::default() };
+        assert_eq!(count(&combinator, &["SE101", "SE102"], &none()), 4);
+        assert_eq!(count(&combinator, &["SE101", "SE102"], &two_hours), 3);
+
+        // SE102 class 1 ends at 12:00.
+        let hour_lunch = Constraints { lunch_break: Some((11 * 60, 13 * 60, 60)), ..Default::default() };
+        let long_lunch = Constraints { lunch_break: Some((11 * 60, 13 * 60, 90)), ..Default::default() };
+        assert_eq!(count(&combinator, &["SE102"], &hour_lunch), 2);
+        assert_eq!(count(&combinator, &["SE102"], &long_lunch), 1);
+        let fix_subs = vec![("SE102".to_string(), 0)];
+        assert_eq!(combinator.count_combinations(&fix_subs, &mut vec![], &mut vec![], &long_lunch, &unlimited()).unwrap(), 0);
+
+        let invalid = Constraints { lunch_break: Some((11 * 60, 12 * 60, 90)), ..Default::default() };
+        assert!(combinator.count_combinations(&vec![], &mut codes(&["SE102"]), &mut vec![], &invalid, &unlimited()).is_err());
+        // 11:00-11:10 has no whole 15 minutes slot.
+        let narrow = Constraints { lunch_break: Some((11 * 60, 11 * 60 + 10, 5)), ..Default::default() };
+        assert!(combinator.count_combinations(&vec![], &mut codes(&["SE102"]), &mut vec![], &narrow, &unlimited()).is_err());
+        let slot = Constraints { lunch_break: Some((11 * 60, 11 * 60 + 15, 5)), ..Default::default() };
+        assert_eq!(count(&combinator, &["SE102"], &slot), 1);
+    }
+
+    #[test]
+    fn test_exclude_and_prof() {
+        use Constraint::Constraints;
+        use Score::Scorer;
+        let mut subs = sample_subjects();
+        subs[3].prof = "홍길동".to_string();
+        let combinator = Tools::SubjectCombinator::new(subs.clone());
+
+        let exclude = Constraints { exclude: vec![("SE102".to_string(), 0)], ..Default::default() };
+        let all: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut codes(&["SE102"]), &mut vec![], &exclude, &unlimited()).unwrap().collect();
+        assert_eq!(all, vec![vec![3]]);
+        let avoid = Constraints { avoid_prof: vec!["홍길동".to_string()], ..Default::default() };
+        let all: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut codes(&["SE102"]), &mut vec![], &avoid, &unlimited()).unwrap().collect();
+        assert_eq!(all, vec![vec![2]]);
+
+        let fix_subs = vec![("SE102".to_string(), 1)];
+        let diagnosis = combinator.explain(&fix_subs, &mut vec![], &mut vec![], &avoid, &unlimited()).unwrap().unwrap();
+        assert_eq!(diagnosis.blocked_fixed, fix_subs);
+        let invalid = Constraints { exclude: vec![("SE102".to_string(), 5)], ..Default::default() };
+        assert!(combinator.count_combinations(&vec![], &mut codes(&["SE102"]), &mut vec![], &invalid, &unlimited()).is_err());
+
+        let prefer = Score::PreferProf { profs: vec!["길동".to_string()] };
+        assert_eq!(prefer.score(&vec![&subs[2], &subs[3]]), 1.0);
+        let top = combinator.combinate_top_k(&vec![], &mut codes(&["SE102"]), &mut vec![], &none(), &unlimited(), 1, &prefer).unwrap().unwrap();
+        assert_eq!(top, vec![vec![3]]);
+    }
+
+    #[test]
+    fn test_groups() {
+        use Constraint::{Constraints, Group};
+        let combinator = Tools::SubjectCombinator::new(sample_subjects());
+        let group = |min: usize, max: Option<usize>| Constraints {
+            groups: vec![Group { subjects: codes(&["SE102", "HL201"]), min: min, max: max }],
+            ..Default::default()
+        };
+        let in_group = |comb: &Vec<usize>| comb.iter().filter(|x| combinator.subjects()[**x].code != "SE101").count();
+
+        let all: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut codes(&["SE101"]), &mut vec![], &group(1, Some(1)), &unlimited()).unwrap().collect();
+        assert_eq!(all.len(), 7);
+        assert!(all.iter().all(|x| in_group(x) == 1));
+        assert_eq!(combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut vec![], &group(2, None), &unlimited()).unwrap(), 6);
+        // group subject in "sel" is the same slot.
+        assert_eq!(combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut codes(&["SE102"]), &group(2, None), &unlimited()).unwrap(), 6);
+
+        // fixed class counts in its group.
+        let fix_subs = vec![("SE102".to_string(), 0)];
+        assert_eq!(combinator.count_combinations(&fix_subs, &mut codes(&["SE101"]), &mut vec![], &group(1, Some(1)), &unlimited()).unwrap(), 2);
+        let diagnosis = combinator.explain(&fix_subs, &mut codes(&["SE101"]), &mut vec![], &group(0, Some(0)), &unlimited()).unwrap().unwrap();
+        assert_eq!(diagnosis.unmet_groups, vec![0]);
+        assert_eq!(diagnosis.blocking_subject, None);
+
+        assert!(combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut vec![], &group(3, None), &unlimited()).is_err());
+        assert!(combinator.count_combinations(&vec![], &mut codes(&["SE101"]), &mut vec![], &group(2, Some(1)), &unlimited()).is_err());
+    }
+
+    #[test]
+    fn test_linked_sections() {
+        use Constraint::Constraints;
+        let mut subs = sample_subjects();
+        let n = subs.len() as u32;
+        let mut lecture = Subject::Subject::new(n + 1, "PH141".to_string(), 1, "물리".to_string(), "교수".to_string(), 3, "월12:00-13:00(E9-101)".to_string());
+        lecture.links = vec![("PH141".to_string(), 11), ("PH141".to_string(), 12)];
+        subs.push(lecture);
+        subs.push(Subject::Subject::new(n + 2, "PH141".to_string(), 11, "물리".to_string(), "교수".to_string(), 1, "화13:00-15:00(E9-201)".to_string()));
+        subs.push(Subject::Subject::new(n + 3, "PH141".to_string(), 12, "물리".to_string(), "교수".to_string(), 1, "수09:00-11:00(E9-201)".to_string()));
+        let (lecture, lab1, lab2) = (subs.len() - 3, subs.len() - 2, subs.len() - 1);
+        let combinator = Tools::SubjectCombinator::new(subs);
+
+        let all: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut codes(&["PH141"]), &mut vec![], &none(), &unlimited()).unwrap().collect();
+        assert_eq!(all, vec![vec![lecture, lab1], vec![lecture, lab2]]);
+        // SE101 class 1 is on 수09:00, class 2 on 화13:00.
+        let all: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut codes(&["PH141", "SE101"]), &mut vec![], &none(), &unlimited()).unwrap().collect();
+        assert_eq!(all.iter().map(sorted).collect::<Vec<_>>(), vec![vec![0, lecture, lab1], vec![1, lecture, lab2]]);
+        let top = combinator.combinate_top_k(&vec![], &mut codes(&["PH141"]), &mut vec![], &none(), &unlimited(), 1, &Score::TotalCredits).unwrap().unwrap();
+        assert_eq!(top[0].len(), 2);
+
+        // lecture cannot be fixed without its lab.
+        let fix_subs = vec![("PH141".to_string(), 0)];
+        assert_eq!(combinator.count_combinations(&fix_subs, &mut vec![], &mut vec![], &none(), &unlimited()).unwrap(), 0);
+        let diagnosis = combinator.explain(&fix_subs, &mut vec![], &mut vec![], &none(), &unlimited()).unwrap().unwrap();
+        assert_eq!(diagnosis.blocked_fixed, fix_subs);
+        let fix_subs = vec![("PH141".to_string(), 0), ("PH141".to_string(), 2)];
+        assert_eq!(combinator.count_combinations(&fix_subs, &mut vec![], &mut vec![], &none(), &unlimited()).unwrap(), 1);
+    }
+
+    // SE0 ~ SE4, 10 classes each. about 10^5 combinations.
+    fn many_subjects() -> Vec<Subject::Subject> {
+        let days = ["월", "화", "수", "목", "금"];
+        let mut subs = Vec::new();
+        for i in 0..5 {
+            for j in 0..10 {
+                let start = 9 * 60 + i * 60 + (j / 5) * 30;
+                let time = format!("{}{:02}:{:02}-{:02}:{:02}(E7-101)", days[j % 5], start / 60, start % 60, (start + 90) / 60, (start + 90) % 60);
+                subs.push(Subject::Subject::new(subs.len() as u32 + 1, format!("SE{}", i), j as u8 + 1, "과목".to_string(), "교수".to_string(), 3, time));
+            }
+        }
+        subs
+    }
+
+    #[test]
+    fn test_parallel_order() {
+        let combinator = Tools::SubjectCombinator::new(many_subjects());
+        let mut req: Vec<String> = (0..5).map(|x| format!("SE{}", x)).collect();
+
+        // big enough to be split, and must be in same order as sequential walk.
+        let all = combinator.combinate_subjects(&vec![], &mut req, &mut vec![], &none(), &unlimited()).unwrap().unwrap();
+        let all: Vec<Vec<usize>> = all.iter().map(|x| x.to_vec()).collect();
+        let iterated: Vec<Vec<usize>> = combinator.iter_combinations(&vec![], &mut req, &mut vec![], &none(), &unlimited()).unwrap().collect();
+        assert!(all.len() > 1000);
+        assert_eq!(all, iterated);
+        assert_eq!(combinator.count_combinations(&vec![], &mut req, &mut vec![], &none(), &unlimited()).unwrap(), all.len());
+    }
+
+    #[test]
+    fn test_budget() {
+        use std::time::Duration;
+        use Tools::Budget;
+        let combinator = Tools::SubjectCombinator::new(many_subjects());
+        let mut req: Vec<String> = (0..5).map(|x| format!("SE{}", x)).collect();
+
+        let budget = Budget::unlimited();
+        let all = combinator.count_combinations(&vec![], &mut req, &mut vec![], &none(), &budget).unwrap();
+        assert!(!budget.is_truncated());
+
+        let budget = Budget::unlimited().with_max_nodes(1000);
+        let partial = combinator.combinate_subjects(&vec![], &mut req, &mut vec![], &none(), &budget).unwrap().unwrap();
+        assert!(budget.is_truncated());
+        assert!(partial.len() < all);
+        let budget = Budget::unlimited().with_max_nodes(1000);
+        assert!(combinator.iter_combinations(&vec![], &mut req, &mut vec![], &none(), &budget).unwrap().count() < all);
+        assert!(budget.is_truncated());
+
+        let budget = Budget::unlimited().with_deadline(Duration::from_secs(0));
+        assert!(combinator.count_combinations(&vec![], &mut req, &mut vec![], &none(), &budget).unwrap() < all);
+        assert!(budget.is_truncated());
+
+        // stopped while iterating
+        let budget = Budget::unlimited();
+        let mut iter = combinator.iter_combinations(&vec![], &mut req, &mut vec![], &none(), &budget).unwrap();
+        assert!(iter.next().is_some());
+        budget.stop();
+        assert!(iter.count() < all);
+    }
+
+    #[test]
+    fn test_grouped() {
+        use Constraint::Constraints;
+        let mut subs = sample_subjects();
+        // same time as SE102 class 1, by other professor.
+        let n = subs.len() as u32;
+        subs.push(Subject::Subject::new(n + 1, "SE102".to_string(), 3, "과목".to_string(), "홍길동".to_string(), 2, "월10:30-12:00(E7-103)".to_string()));
+        let combinator = Tools::SubjectCombinator::new(subs);
+        let budget = Tools::Budget::unlimited();
+
+        let all = combinator.count_combinations(&vec![], &mut codes(&["SE101", "SE102"]), &mut vec![], &none(), &budget).unwrap();
+        let grouped = combinator.combinate_grouped(&vec![], &mut codes(&["SE101", "SE102"]), &mut vec![], &none(), &budget).unwrap().unwrap();
+        assert_eq!(all, 6);
+        assert_eq!(grouped.len(), 4);
+        assert_eq!(grouped.iter().map(|x| x.iter().map(|y| y.len()).product::<usize>()).sum::<usize>(), all);
+        assert!(grouped.contains(&vec![vec![0], vec![2, 6]]));
+
+        // excluded class is not in the group.
+        let exclude = Constraints { exclude: vec![("SE102".to_string(), 0)], ..Default::default() };
+        let grouped = combinator.combinate_grouped(&vec![], &mut codes(&["SE101", "SE102"]), &mut vec![], &exclude, &budget).unwrap().unwrap();
+        assert!(grouped.contains(&vec![vec![0], vec![6]]));
+    }
+
+    fn check_store(store: &dyn DB::ShareStore) {
+        let catalog = DB::Catalog::new(&sample_subjects());
+        let get = |key: &str, ttl: u64| DB::get_share(store, &catalog, &key.to_string(), ttl);
+        let (key, _) = DB::add_share(store, &catalog, &DB::IdFormat::default(), &vec![3, 1, 4], Some(DB::DEFAULT_TTL)).unwrap();
+        let share = get(&key, DB::DEFAULT_TTL).unwrap();
+        assert_eq!(share.comb, vec![3, 1, 4]);
+        assert!(!store.put_new(&key, &share).unwrap());
+        assert_eq!(get("nothing", DB::DEFAULT_TTL), Err(DB::ShareError::NotFound));
+        DB::del_share(store, &key);
+        assert_eq!(get(&key, DB::DEFAULT_TTL), Err(DB::ShareError::NotFound));
+        assert!(DB::add_share(store, &catalog, &DB::IdFormat::default(), &vec![100], None).is_err());
+
+        // expired share is gone
+        let old = DB::Share { version: 0, comb: vec![1], catalog: String::new(), classes: vec![], expires: Some(DB::now() - 1), owner: String::new() };
+        store.put("old", &old).unwrap();
+        assert_eq!(get("old", DB::DEFAULT_TTL), Err(DB::ShareError::NotFound));
+        assert!(!store.exists("old").unwrap());
+        assert!(store.put_new("old", &old).unwrap());
+
+        // touch changes only expiry, and never brings back deleted share
+        let (key, share) = DB::add_share(store, &catalog, &DB::IdFormat::default(), &vec![0], Some(10)).unwrap();
+        store.put(&key, &DB::Share { comb: vec![2], ..share.clone() }).unwrap();
+        store.touch(&key, share.expires.unwrap() + 100).unwrap();
+        let touched = store.get(&key).unwrap().unwrap();
+        assert_eq!((touched.comb, touched.expires), (vec![2], Some(share.expires.unwrap() + 100)));
+        DB::del_share(store, &key);
+        store.touch(&key, DB::now() + 100).unwrap();
+        assert_eq!(get(&key, DB::DEFAULT_TTL), Err(DB::ShareError::NotFound));
+
+        // reading puts off the expiry, pinned share never expires
+        let (key, share) = DB::add_share(store, &catalog, &DB::IdFormat::default(), &vec![5], Some(10)).unwrap();
+        let expires = share.expires.unwrap();
+        assert!(expires >= DB::now() + 10);
+        assert!(get(&key, 1000).unwrap().expires.unwrap() >= expires + 990 - 10);
+        let (key, share) = DB::add_share(store, &catalog, &DB::IdFormat::default(), &vec![5], None).unwrap();
+        assert_eq!(share.expires, None);
+        assert_eq!(get(&key, 1000).unwrap().expires, None);
+    }
+
+    #[test]
+    fn test_share_owner() {
+        let store = DB::MemoryStore::new();
+        let catalog = DB::Catalog::new(&sample_subjects());
+        let (key, share) = DB::add_share(&store, &catalog, &DB::IdFormat::default(), &vec![0, 4], Some(100)).unwrap();
+        let token = share.owner.clone();
+        assert_eq!(token.len(), 32);
+
+        assert_eq!(DB::update_share(&store, &catalog, &key, "wrong", &vec![1], None, 100), Err(DB::ShareError::Forbidden));
+        assert_eq!(DB::update_share(&store, &catalog, &key, &token, &vec![100], None, 100), Err(DB::ShareError::UnknownClass));
+        let share = DB::update_share(&store, &catalog, &key, &token, &vec![1, 5], Some(true), 100).unwrap();
+        assert_eq!(share.expires, None);
+        assert_eq!(DB::get_share(&store, &catalog, &key, 100).unwrap().comb, vec![1, 5]);
+
+        assert_eq!(DB::delete_share(&store, &key, ""), Err(DB::ShareError::Forbidden));
+        assert_eq!(DB::delete_share(&store, &key, &token), Ok(()));
+        assert_eq!(DB::delete_share(&store, &key, &token), Err(DB::ShareError::NotFound));
+
+        // old share has no owner
+        let old = DB::Share { version: 0, comb: vec![2], catalog: String::new(), classes: vec![], expires: None, owner: String::new() };
+        store.put("old", &old).unwrap();
+        assert_eq!(DB::delete_share(&store, &"old".to_string(), ""), Err(DB::ShareError::Forbidden));
+    }
+
+    #[test]
+    fn test_share_id() {
+        let store = DB::MemoryStore::new();
+        let catalog = DB::Catalog::new(&sample_subjects());
+        let format = DB::IdFormat::new(DB::IdAlphabet::Crockford, 6, true).unwrap();
+        let (key, _) = DB::add_share(&store, &catalog, &format, &vec![0], None).unwrap();
+        assert_eq!(key.len(), 7);
+        assert!(key.chars().all(|x| "0123456789ABCDEFGHJKMNPQRSTVWXYZ".contains(x)));
+        assert_eq!(format.parse(&key.to_lowercase()), Ok(key.clone()));
+
+        // any one wrong character is caught
+        for i in 0..key.len() {
+            let mut typo: Vec<char> = key.chars().collect();
+            typo[i] = if typo[i] == 'Z' {'Y'} else {'Z'};
+            let typo: String = typo.into_iter().collect();
+            assert_eq!(format.parse(&typo), Err(DB::ShareError::InvalidId));
+        }
+        // old id is kept as it is
+        assert_eq!(format.parse("a!b(c)d"), Ok("a!b(c)d".to_string()));
+        // old id which looks like Crockford id is found as it is.
+        let share = DB::get_share(&store, &catalog, &key, 0).unwrap();
+        store.put("aBcdEfg", &share).unwrap();
+        assert_eq!(format.find(&store, "aBcdEfg"), Ok("aBcdEfg".to_string()));
+        let no_checksum = DB::IdFormat::new(DB::IdAlphabet::Crockford, 7, false).unwrap();
+        assert_eq!(no_checksum.parse("aBcdEfg"), Ok("ABCDEFG".to_string()));
+        assert_eq!(no_checksum.find(&store, "aBcdEfg"), Ok("aBcdEfg".to_string()));
+        assert_eq!(format.find(&store, &key.to_lowercase()), Ok(key.clone()));
+        assert!(DB::IdFormat::new(DB::IdAlphabet::Base62, 0, false).is_err());
+
+        // taken id is not overwritten
+        let share = DB::get_share(&store, &catalog, &key, 0).unwrap();
+        assert!(!store.put_new(&key, &share).unwrap());
+        assert!(store.put_new("other", &share).unwrap());
+    }
+
+    #[test]
+    fn test_share_catalog() {
+        let store = DB::MemoryStore::new();
+        let catalog = DB::Catalog::new(&sample_subjects());
+        // idx 0: SE101-1, idx 4: HL201-1
+        let (key, share) = DB::add_share(&store, &catalog, &DB::IdFormat::default(), &vec![0, 4], None).unwrap();
+        assert_eq!(share.classes, vec![("SE101".to_string(), 1), ("HL201".to_string(), 1)]);
+
+        // re-crawled catalog in other order
+        let mut subjects = sample_subjects();
+        subjects.reverse();
+        for (i, sub) in subjects.iter_mut().enumerate() {
+            sub.number = i as u32 + 1;
+        }
+        let recrawled = DB::Catalog::new(&subjects);
+        assert_ne!(catalog.fingerprint(), recrawled.fingerprint());
+        assert_eq!(DB::get_share(&store, &recrawled, &key, 0).unwrap().comb, vec![5, 1]);
+
+        // idx is position in subject vector, also after a row is skipped at load.
+        let skipped: Vec<Subject::Subject> = sample_subjects().into_iter().filter(|x| x.number != 2).collect();
+        let skipped = DB::Catalog::new(&skipped);
+        assert_eq!(DB::get_share(&store, &skipped, &key, 0).unwrap().comb, vec![0, 3]);
+
+        // next semester without SE101
+        subjects.retain(|x| x.code != "SE101");
+        let next = DB::Catalog::new(&subjects);
+        assert_eq!(DB::get_share(&store, &next, &key, 0), Err(DB::ShareError::Stale(vec![("SE101".to_string(), 1)])));
+
+        // old share without catalog is taken as of current catalog
+        let old = DB::Share { version: 0, comb: vec![2], catalog: String::new(), classes: vec![], expires: None, owner: String::new() };
+        store.put("old", &old).unwrap();
+        let share = DB::get_share(&store, &catalog, &"old".to_string(), 0).unwrap();
+        assert_eq!((share.version, share.classes), (DB::SHARE_VERSION, vec![("SE102".to_string(), 1)]));
+        let old = DB::Share { comb: vec![100], ..old };
+        store.put("old", &old).unwrap();
+        assert_eq!(DB::get_share(&store, &catalog, &"old".to_string(), 0), Err(DB::ShareError::UnknownClass));
+    }
+
+    // reads while the owner deletes or updates the share never undo it.
+    fn check_race(store: &dyn DB::ShareStore) {
+        let catalog = DB::Catalog::new(&sample_subjects());
+        // old record, which reads move to the catalog.
+        let old = DB::Share { version: 0, comb: vec![1], catalog: String::new(), classes: vec![], expires: Some(DB::now() + 100), owner: "owner".to_string() };
+        for i in 0..20 {
+            let key = format!("race{}", i);
+            store.put(&key, &old).unwrap();
+            std::thread::scope(|s| {
+                s.spawn(|| for _ in 0..20 {let _ = DB::get_share(store, &catalog, &key, 100);});
+                s.spawn(|| assert_eq!(DB::delete_share(store, &key, "owner"), Ok(())));
+            });
+            assert_eq!(DB::get_share(store, &catalog, &key, 100), Err(DB::ShareError::NotFound));
+
+            store.put(&key, &old).unwrap();
+            std::thread::scope(|s| {
+                s.spawn(|| for _ in 0..20 {let _ = DB::get_share(store, &catalog, &key, 100);});
+                s.spawn(|| assert!(DB::update_share(store, &catalog, &key, "owner", &vec![2, 4], None, 100).is_ok()));
+            });
+            assert_eq!(DB::get_share(store, &catalog, &key, 100).unwrap().comb, vec![2, 4]);
+
+            // reads do not undo pin of owner.
+            let (key, share) = DB::add_share(store, &catalog, &DB::IdFormat::default(), &vec![1], Some(100)).unwrap();
+            std::thread::scope(|s| {
+                s.spawn(|| for _ in 0..20 {let _ = DB::get_share(store, &catalog, &key, 100);});
+                s.spawn(|| assert!(DB::update_share(store, &catalog, &key, &share.owner, &vec![1], Some(true), 100).is_ok()));
+            });
+            assert_eq!(DB::get_share(store, &catalog, &key, 100).unwrap().expires, None);
+        }
+    }
+
+    #[test]
+    fn test_share_store() {
+        check_store(DB::open_store("memory").unwrap().as_ref());
+        check_race(DB::open_store("memory").unwrap().as_ref());
+
+        let path = std::env::temp_dir().join(format!("teatable-share-{}", std::process::id()));
+        let store = DB::open_store(&format!("sled:{}", path.display())).unwrap();
+        check_store(store.as_ref());
+        check_race(store.as_ref());
+        drop(store);
+        let _ = std::fs::remove_dir_all(&path);
+
+        // broken record is an error of the store, not a missing share.
+        let db = sled::open(&path).unwrap();
+        db.insert("broken", "[1,".as_bytes()).unwrap();
+        drop(db);
+        let store = DB::open_store(&format!("sled:{}", path.display())).unwrap();
+        let catalog = DB::Catalog::new(&sample_subjects());
+        assert_eq!(DB::get_share(store.as_ref(), &catalog, &"broken".to_string(), 0), Err(DB::ShareError::Store("Broken share")));
+        drop(store);
+        let _ = std::fs::remove_dir_all(&path);
+
+        assert!(DB::open_store("mysql://localhost").is_err());
+    }
+}
@@ -3,6 +3,7 @@ use actix_web::http::header::{ContentType};
 use openssl::ssl::{SslConnector, SslVerifyMode, SslMethod};
 use serde::{Serialize, Deserialize};
 use backend::Subject::*;
+use std::collections::HashMap;
 
 const SUBJECT_URL: &'static str = "https://welcome.dgist.ac.kr/ucs/ucsqProfRespSbjtInq/list.do;";
 
@@ -35,8 +36,18 @@ pub struct SubjectResponse {
 }
 
 impl SubjectResponse {
+    /// Rows which cannot be parsed are skipped with warning.
     pub fn to_subject_vector(self) -> Vec<Subject> {
-        self.user.into_iter().map(|x| x.to_subject()).collect()
+        let mut subjects = Vec::with_capacity(self.user.len());
+        for unit in self.user.into_iter() {
+            let name = format!("{}-{} ({})", &unit.SBJT_NO, &unit.CLSS_NO, &unit.SBJT_NM);
+            match unit.to_subject() {
+                Ok(t) => subjects.push(t),
+                Err(e) => warn!("Skip {}: {}", name, e)
+            }
+        }
+        link_labs(&mut subjects);
+        subjects
     }
 }
 
@@ -71,17 +82,49 @@ pub struct ResponseUnit {
     RNUM: u32
 }
 
+// Lab sections are taken with a lecture section of the same subject.
+fn is_lab(kind: &str) -> bool {
+    kind == "실습" || kind == "실험"
+}
+
+// Every lecture section links to lab sections of its subject, if the subject has both.
+fn link_labs(subjects: &mut Vec<Subject>) {
+    let mut labs: HashMap<String, Vec<(String, u8)>> = HashMap::new();
+    for sub in subjects.iter().filter(|x| is_lab(&x.kind)) {
+        labs.entry(sub.code.clone()).or_insert_with(Vec::new).push((sub.code.clone(), sub.class_num));
+    }
+    for sub in subjects.iter_mut().filter(|x| !is_lab(&x.kind)) {
+        if let Some(t) = labs.get(&sub.code) {
+            sub.links = t.clone();
+        }
+    }
+}
+
 impl ResponseUnit {
-    pub fn to_subject(self) -> Subject {
-        Subject::new(
+    pub fn to_subject(self) -> Result<Subject, String> {
+        let class_num = match self.CLSS_NO.parse::<u8>() {
+            Ok(t) => t,
+            Err(_) => return Err(format!("Invalid class number {}", &self.CLSS_NO))
+        };
+        let credit = match self.PNT.parse::<f32>() {
+            Ok(t) => t as u8,
+            Err(_) => return Err(format!("Invalid credit {}", &self.PNT))
+        };
+        match Subject::try_new(
             self.RNUM,
             self.SBJT_NO,
-            self.CLSS_NO.parse::<u8>().unwrap(),
+            class_num,
             self.SBJT_NM,
             self.PROF_NM,
-            self.PNT.parse::<f32>().unwrap() as u8,
+            credit,
             self.TLSN_TIME.unwrap_or("".to_string())
-        )
+        ) {
+            Ok(mut t) => {
+                t.kind = self.LT_PRAC_NM;
+                Ok(t)
+            },
+            Err(e) => Err(e.to_string())
+        }
     }
 }
 
@@ -27,6 +27,24 @@ pub fn filter_query(req: &Vec<String>, sel: &Vec<String>) -> bool{
     true
 }
 
+// Most subjects searched in one query, required, selected and of elective groups together.
+const MAX_SUBJECTS: usize = 20;
+
+// Subjects of elective groups are also searched, so they are counted with "req" and "sel".
+pub fn filter_groups(req: &Vec<String>, sel: &Vec<String>, constraints: &backend::Constraint::Constraints) -> bool {
+    let mut subs: Vec<String> = constraints.groups.iter().flat_map(|x| x.subjects.iter().cloned()).collect();
+    subs.sort_unstable();
+    subs.dedup();
+    if !filter_query(&vec![], &subs) {
+        return false
+    }
+    subs.extend(req.iter().cloned());
+    subs.extend(sel.iter().cloned());
+    subs.sort_unstable();
+    subs.dedup();
+    subs.len() <= MAX_SUBJECTS
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -55,4 +73,18 @@ mod tests {
         }
         assert_eq!(filter_query(&req, &sel), true);
     }
+
+    #[test]
+    fn test_filter_groups() {
+        use backend::Constraint::{Constraints, Group};
+        let names = |p: &str, n: usize| (0..n).map(|x| format!("{}{}", p, x)).collect::<Vec<String>>();
+        let group = |subjects: Vec<String>| Constraints { groups: vec![Group { subjects: subjects, min: 1, max: None }], ..Default::default() };
+        let (req, sel) = (names("SE", 10), names("HL", 10));
+        assert_eq!(filter_groups(&req, &sel, &Constraints::default()), true);
+        assert_eq!(filter_groups(&req, &sel, &group(names("PH", 1))), false);
+        // same subjects are counted once.
+        assert_eq!(filter_groups(&req, &sel, &group(names("SE", 3))), true);
+        assert_eq!(filter_groups(&vec![], &vec![], &group(names("PH", 11))), false);
+        assert_eq!(filter_groups(&vec![], &vec![], &group(vec!["BS900".to_string()])), false);
+    }
 }
\ No newline at end of file
@@ -7,6 +7,10 @@ static GLOBAL: Jemalloc = Jemalloc;
 
 use backend;
 use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::web::Bytes;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::{SinkExt, Stream};
 use actix_cors::Cors;
 
 #[macro_use] extern crate log;
@@ -15,10 +19,17 @@ use actix_web::middleware::Logger;
 
 use serde_json::json;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
 use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use backend::Tools::Budget;
+use backend::Subject::TimeGrid;
 
-use r2d2_redis::{r2d2, RedisConnectionManager};
+use backend::DB::{Catalog, IdAlphabet, IdFormat, ShareError, ShareStore};
 
 mod crawler;
 mod filter;
@@ -26,77 +37,348 @@ mod filter;
 #[derive(Deserialize)]
 struct DBJson {
     id: Option<String>,
-    save: Option<Vec<u32>>
+    save: Option<Vec<u32>>,
+    // saved share never expires.
+    pin: Option<bool>,
+    // owner token of "id", to update ("save") or delete it.
+    token: Option<String>,
+    delete: Option<bool>
 }
 
-async fn db_access(json: web::Json<DBJson>, conn_pool: web::Data<r2d2::Pool<RedisConnectionManager>>) -> HttpResponse {
-    match &json.id {
-        Some(t) => {
-            match backend::DB::get_share(conn_pool.as_ref(), &t) {
-                Some(comb) => {
-                    let comb = vec!(comb);
-                    let res = json!({"s":"s", "comb" :comb}).to_string();
-                    return HttpResponse::Ok().body(res);
+#[derive(Clone)]
+struct ShareConfig {
+    // seconds to keep a share after it is last read.
+    ttl: u64,
+    id_format: IdFormat
+}
+
+impl ShareConfig {
+    // SHARE_TTL: seconds
+    // SHARE_ID_ALPHABET: "base62" or "crockford", SHARE_ID_LEN, SHARE_ID_CHECKSUM: "true" to add check character
+    fn from_env() -> Self {
+        let ttl = env::var("SHARE_TTL").ok().and_then(|x| x.parse().ok()).unwrap_or(backend::DB::DEFAULT_TTL);
+        let default = IdFormat::default();
+        let alphabet = match env::var("SHARE_ID_ALPHABET").as_ref().map(|x| x.as_str()) {
+            Ok("crockford") => IdAlphabet::Crockford,
+            _ => default.alphabet
+        };
+        let len = env::var("SHARE_ID_LEN").ok().and_then(|x| x.parse().ok()).unwrap_or(default.len);
+        let checksum = env::var("SHARE_ID_CHECKSUM").map(|x| x == "true").unwrap_or(default.checksum);
+        ShareConfig {
+            ttl: ttl,
+            id_format: IdFormat::new(alphabet, len, checksum).unwrap()
+        }
+    }
+}
+
+// GRID_START_HOUR, GRID_END_HOUR, GRID_SLOT_MINUTES: day window and time resolution of time bits.
+fn grid_from_env() -> TimeGrid {
+    let default = TimeGrid::default();
+    let var = |name: &str, default: u32| env::var(name).ok().and_then(|x| x.parse().ok()).unwrap_or(default);
+    TimeGrid::new(var("GRID_START_HOUR", default.start_hour), var("GRID_END_HOUR", default.end_hour), var("GRID_SLOT_MINUTES", default.slot_minutes)).unwrap()
+}
+
+fn share_error(e: ShareError) -> HttpResponse {
+    let res = match e {
+        ShareError::Stale(missing) => json!({"s":"f", "msg" :"지난 학기 시간표라 가져올 수 없습니다.", "stale": true, "missing": missing}),
+        ShareError::Forbidden => json!({"s":"f", "msg" :"수정 권한이 없습니다."}),
+        ShareError::UnknownClass => json!({"s":"f", "msg" :"부적합한 쿼리입니다."}),
+        ShareError::Store(_) => json!({"s":"f", "msg" :"저장되지 못했습니다."}),
+        ShareError::InvalidId => json!({"s":"f", "msg" :"잘못된 주소입니다."}),
+        ShareError::NotFound => json!({"s":"f", "msg" :"가져오기에 실패했습니다."}),
+    };
+    HttpResponse::Ok().body(res.to_string())
+}
+
+async fn db_access(json: web::Json<DBJson>, store: web::Data<Box<dyn ShareStore>>, catalog: web::Data<Catalog>, config: web::Data<ShareConfig>) -> HttpResponse {
+    let store = store.as_ref().as_ref();
+    // delete without token is not a read.
+    if json.delete.unwrap_or(false) && json.token.is_none() {
+        return share_error(ShareError::Forbidden);
+    }
+    let id = match json.id.as_ref().map(|x| config.id_format.find(store, x)) {
+        Some(Err(e)) => return share_error(e),
+        Some(Ok(id)) => Some(id),
+        None => None
+    };
+    match (&id, &json.token, &json.save) {
+        (Some(id), Some(token), _) if json.delete.unwrap_or(false) => {
+            match backend::DB::delete_share(store, &id, &token) {
+                Ok(_) => HttpResponse::Ok().body(json!({"s":"s"}).to_string()),
+                Err(e) => share_error(e)
+            }
+        },
+        (Some(id), Some(token), Some(v)) => {
+            match backend::DB::update_share(store, &catalog, &id, &token, &v, json.pin, config.ttl) {
+                Ok(share) => {
+                    let res = json!({"s":"s", "id" :id, "expires": share.expires}).to_string();
+                    HttpResponse::Ok().body(res)
                 },
-                None => {
-                    let res = json!({"s":"f", "msg" :"가져오기에 실패했습니다."}).to_string();
-                    return HttpResponse::Ok().body(res);
-                }
+                Err(e) => share_error(e)
             }
         },
-        None => {
-            match &json.save {
-                Some(v) => {
-                    match backend::DB::add_share(conn_pool.as_ref(), &v) {
-                        Ok(id) => {
-                            let res = json!({"s":"s", "id" :id}).to_string();
-                            return HttpResponse::Ok().body(res);
-                        },
-                        Err(_) => {
-                            let res = json!({"s":"f", "msg" :"저장되지 못했습니다."}).to_string();
-                            return HttpResponse::Ok().body(res);
-                        }
-                    }
+        (Some(id), None, None) => {
+            match backend::DB::get_share(store, &catalog, &id, config.ttl) {
+                Ok(share) => {
+                    let comb = vec!(share.comb);
+                    let res = json!({"s":"s", "comb" :comb, "expires": share.expires}).to_string();
+                    HttpResponse::Ok().body(res)
                 },
-                None => {
-                    let res = json!({"s":"f", "msg" :"부적합한 쿼리입니다."}).to_string();
-                    return HttpResponse::Ok().body(res);
+                Err(e) => share_error(e)
+            }
+        },
+        (None, None, Some(v)) => {
+            let ttl = if json.pin.unwrap_or(false) {None} else {Some(config.ttl)};
+            match backend::DB::add_share(store, &catalog, &config.id_format, &v, ttl) {
+                Ok((id, share)) => {
+                    let res = json!({"s":"s", "id" :id, "token": share.owner, "expires": share.expires}).to_string();
+                    HttpResponse::Ok().body(res)
+                },
+                Err(_) => {
+                    let res = json!({"s":"f", "msg" :"저장되지 못했습니다."}).to_string();
+                    HttpResponse::Ok().body(res)
                 }
             }
+        },
+        _ => {
+            let res = json!({"s":"f", "msg" :"부적합한 쿼리입니다."}).to_string();
+            HttpResponse::Ok().body(res)
         }
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    // combinations themselves.
+    List,
+    // only number of combinations.
+    Count,
+    // why there is no combination.
+    Explain,
+    // each timetable shape once, with interchangeable classes.
+    Grouped,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::List
+    }
+}
+
 #[derive(Deserialize)]
 struct CombinationJson {
     fix: Vec<(String, usize)>,
     req: Vec<String>,
     sel: Vec<String>,
+    // weights of ranking scorers. ex: {"days": 1.0, "gap": 0.5}
+    #[serde(default)]
+    sort: HashMap<String, f64>,
+    // return only best "top" combinations.
+    top: Option<usize>,
+    #[serde(default)]
+    mode: Mode,
+    // page of result. combinations are always in same order for same query.
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    // hard conditions on time of classes. see backend::Constraint::Constraints
+    #[serde(default)]
+    constraints: backend::Constraint::Constraints,
+    // professors to prefer in ranking. weight is sort["prof"], 1.0 if not given.
+    #[serde(default)]
+    prefer_prof: Vec<String>,
+    // sections never taken, as "fix". same as constraints.exclude.
+    #[serde(default)]
+    exclude: Vec<(String, usize)>,
+    // professors never taken. same as constraints.avoid_prof.
+    #[serde(default)]
+    avoid_prof: Vec<String>,
+}
+
+impl CombinationJson {
+    // options about sections and professors are given beside "prefer_prof", and checked as constraints.
+    fn into_query(mut self) -> Self {
+        self.constraints.exclude.append(&mut self.exclude);
+        self.constraints.avoid_prof.append(&mut self.avoid_prof);
+        self
+    }
+}
+
+// Search of one query stops after this time, and answers combinations found so far with "truncated".
+const SEARCH_TIME: Duration = Duration::from_secs(3);
+// Stream waits for the client, so it is limited by search nodes instead of time.
+const STREAM_NODES: u64 = 100_000_000;
+
+fn comb_response<T: serde::Serialize>(comb: Vec<T>, offset: usize, truncated: bool) -> String {
+    if comb.len() == 0 && offset == 0 && !truncated {
+        json!({"s":"f", "msg":"조합이 없습니다."}).to_string()
+    }
+    else {
+        json!({"s":"t", "comb":comb, "truncated":truncated}).to_string()
+    }
 }
 
 async fn combination(json: web::Json<CombinationJson>, combinator: web::Data<backend::Tools::SubjectCombinator>) -> HttpResponse
 {
-    let CombinationJson{ fix, mut req, mut sel } = json.into_inner();
-    if !filter::filter_query(&req, &sel) {
+    let CombinationJson{ fix, mut req, mut sel, mut sort, top, mode, offset, limit, constraints, prefer_prof, .. } = json.into_inner().into_query();
+    if !filter::filter_query(&req, &sel) || !filter::filter_groups(&req, &sel, &constraints) {
         return HttpResponse::Ok().body(json!({"s":"f", "msg":"잘못된 쿼리 입니다."}).to_string())
     }
-    let ans = combinator.combinate_subjects(&fix, &mut req, &mut sel);
+    let prof_weight = sort.remove("prof").unwrap_or(1.0);
+    let mut scorer = match backend::Score::Weighted::from_names(sort.iter()) {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::Ok().body(json!({"s":"f", "msg":"잘못된 정렬 기준입니다."}).to_string())
+    };
+    if prefer_prof.len() != 0 {
+        scorer = scorer.add(prof_weight, Box::new(backend::Score::PreferProf { profs: prefer_prof }));
+    }
 
-    let res: String = match ans {
-        Ok(value) => match value {
-            Some(arr) => {
-                let comb: Vec<&Vec<usize>> = arr.iter().map(
-                        |x| x.as_ref()
-                    ).collect();
-                json!({"s":"t", "comb":comb}).to_string()
+    let budget = Budget::unlimited().with_deadline(SEARCH_TIME);
+    if let Mode::Count = mode {
+        let res = match combinator.count_combinations(&fix, &mut req, &mut sel, &constraints, &budget) {
+            Ok(count) => json!({"s":"t", "count":count, "truncated":budget.is_truncated()}).to_string(),
+            Err(_) => json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string()
+        };
+        return HttpResponse::Ok().body(res)
+    }
+    if let Mode::Explain = mode {
+        let res = match combinator.explain(&fix, &mut req, &mut sel, &constraints, &budget) {
+            Ok(diagnosis) => json!({"s":"t", "explain":diagnosis, "truncated":budget.is_truncated()}).to_string(),
+            Err(_) => json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string()
+        };
+        return HttpResponse::Ok().body(res)
+    }
+
+    if let Mode::Grouped = mode {
+        let res = match combinator.combinate_grouped(&fix, &mut req, &mut sel, &constraints, &budget) {
+            Ok(grouped) => {
+                let grouped: Vec<_> = grouped.unwrap_or_default().into_iter().skip(offset).take(limit.unwrap_or(usize::MAX)).collect();
+                if grouped.len() == 0 && offset == 0 && !budget.is_truncated() {
+                    json!({"s":"f", "msg":"조합이 없습니다."}).to_string()
+                }
+                else {
+                    json!({"s":"t", "grouped":grouped, "truncated":budget.is_truncated()}).to_string()
+                }
             },
-            None => json!({"s":"f", "msg":"조합이 없습니다."}).to_string(),
-        },
-        Err(_) => json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string()
+            Err(_) => json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string()
+        };
+        return HttpResponse::Ok().body(res)
+    }
+
+    let res: String = if top.is_some() || (limit.is_some() && !scorer.is_empty()) {
+        // best "offset + limit" combinations are enough for the page.
+        let mut k = top.unwrap_or(usize::MAX);
+        if let Some(l) = limit {
+            k = k.min(offset.saturating_add(l));
+        }
+        match combinator.combinate_top_k(&fix, &mut req, &mut sel, &constraints, &budget, k, &scorer) {
+            Ok(comb) => comb_response(comb.unwrap_or_default().into_iter().skip(offset).collect(), offset, budget.is_truncated()),
+            Err(_) => json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string()
+        }
+    }
+    else if let Some(l) = limit {
+        match combinator.iter_combinations(&fix, &mut req, &mut sel, &constraints, &budget) {
+            Ok(iter) => {
+                let comb: Vec<Vec<usize>> = iter.skip(offset).take(l).collect();
+                comb_response(comb, offset, budget.is_truncated())
+            },
+            Err(_) => json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string()
+        }
+    }
+    else {
+        match combinator.combinate_subjects(&fix, &mut req, &mut sel, &constraints, &budget) {
+            Ok(value) => match value {
+                Some(arr) => {
+                    let mut comb: Vec<&Vec<usize>> = arr.iter().map(
+                            |x| x.as_ref()
+                        ).collect();
+                    if !scorer.is_empty() {
+                        combinator.rank_combinations(&mut comb, &scorer);
+                    }
+                    comb_response(comb.into_iter().skip(offset).collect(), offset, budget.is_truncated())
+                },
+                None => comb_response(Vec::<usize>::new(), 0, budget.is_truncated()),
+            },
+            Err(_) => json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string()
+        }
     };
     HttpResponse::Ok().body(res)
 }
 
+// Number of combinations written in one chunk of stream.
+const STREAM_CHUNK: usize = 64;
+
+// Same query as "combination", but each combination is written as one line of json (NDJSON) as soon as it is found.
+// "sort" and "top" are ignored. If search stops by budget, last line is {"truncated":true}.
+async fn combination_stream(json: web::Json<CombinationJson>, combinator: web::Data<backend::Tools::SubjectCombinator>) -> HttpResponse
+{
+    let CombinationJson{ fix, mut req, mut sel, constraints, mode, offset, limit, .. } = json.into_inner().into_query();
+    if !filter::filter_query(&req, &sel) || !filter::filter_groups(&req, &sel, &constraints) {
+        return HttpResponse::Ok().body(json!({"s":"f", "msg":"잘못된 쿼리 입니다."}).to_string())
+    }
+    // stream is only the list of combinations, from the first.
+    if !matches!(mode, Mode::List) || offset != 0 || limit.is_some() {
+        return HttpResponse::Ok().body(json!({"s":"f", "msg":"스트림에서 지원하지 않는 쿼리입니다."}).to_string())
+    }
+    if combinator.iter_combinations(&fix, &mut req, &mut sel, &constraints, &Budget::unlimited()).is_err() {
+        return HttpResponse::Ok().body(json!({"s":"f", "msg": "조합에 실패했습니다."}).to_string())
+    }
+
+    // search runs on blocking thread pool of actix, and waits while the client is slower than search.
+    let (mut tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(4);
+    let budget = Arc::new(Budget::unlimited().with_max_nodes(STREAM_NODES));
+    let body = StreamBody { rx: rx, budget: budget.clone() };
+    let combinator = combinator.clone();
+    actix_rt::spawn(async move {
+        let _ = web::block(move || -> Result<(), ()> {
+            let mut chunk = String::new();
+            let mut count = 0;
+            for comb in combinator.iter_combinations(&fix, &mut req, &mut sel, &constraints, &budget).unwrap() {
+                // client is gone.
+                if tx.is_closed() {return Ok(());}
+                chunk += &json!(comb).to_string();
+                chunk.push('\n');
+                count += 1;
+                if count == STREAM_CHUNK {
+                    if block_on(tx.send(Ok(Bytes::from(chunk)))).is_err() {return Ok(());}
+                    chunk = String::new();
+                    count = 0;
+                }
+            }
+            if budget.is_truncated() && !tx.is_closed() {
+                chunk += &json!({"truncated":true}).to_string();
+                chunk.push('\n');
+            }
+            if chunk.len() != 0 {
+                let _ = block_on(tx.send(Ok(Bytes::from(chunk))));
+            }
+            Ok(())
+        }).await;
+    });
+    HttpResponse::Ok().content_type("application/x-ndjson").streaming(body)
+}
+
+// Body of /api/comb/stream. When the client is gone, it is dropped and stops the search.
+struct StreamBody {
+    rx: mpsc::Receiver<Result<Bytes, std::io::Error>>,
+    budget: Arc<Budget>,
+}
+
+impl Stream for StreamBody {
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+impl Drop for StreamBody {
+    fn drop(&mut self) {
+        self.budget.stop();
+    }
+}
+
 async fn data(data: web::Data<String>) -> HttpResponse
 {
     HttpResponse::Ok().body(data.as_ref())
@@ -106,7 +388,7 @@ async fn data(data: web::Data<String>) -> HttpResponse
 #[cfg(target_os = "linux")]
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    std::env::set_var("RUST_LOG", "actix_web=info");
+    std::env::set_var("RUST_LOG", "actix_web=info,server=info");
     env_logger::init();
 
     use backend::Subject::Subject as Subject;
@@ -115,31 +397,36 @@ async fn main() -> std::io::Result<()> {
     let default_bind = "/tmp/actix.socket".to_string();
     let bind = args.get(1).unwrap_or(&default_bind);
 
-    let subject_vec: Vec<Subject>;
-
-    if Path::new("data.json").exists() {
-        subject_vec = Subject::load("data.json");
-    }
-    else {
+    if !Path::new("data.json").exists() {
         let a = crawler::SubjectQuery::new(2020).fall().undergraduate().send().await.unwrap();
-        subject_vec = a.to_subject_vector();
-        backend::Subject::Subject::save(&subject_vec, "data.json");
+        backend::Subject::Subject::save(&a.to_subject_vector(), "data.json");
+    }
+    // time bits are encoded again with the grid of the combinator.
+    let grid = grid_from_env();
+    let (subject_vec, skipped) = Subject::load_with_grid("data.json", &grid);
+    for (sub, e) in skipped.iter() {
+        warn!("Skip {}-{} ({}): {}", &sub.code, &sub.class_num, &sub.class_name, e);
     }
 
-    let conn_pool = r2d2::Pool::builder().build(
-        RedisConnectionManager::new("redis://127.0.0.1/").unwrap()
-    ).unwrap();
+    // SHARE_STORE: "memory", "sled:<path>" or "redis://<host>/"
+    let store_config = env::var("SHARE_STORE").unwrap_or("redis://127.0.0.1/".to_string());
+    let store: web::Data<Box<dyn ShareStore>> = web::Data::new(backend::DB::open_store(&store_config).unwrap());
+    let share_config = ShareConfig::from_env();
 
-    let combinator = backend::Tools::SubjectCombinator::new(subject_vec.clone());
+    let combinator = backend::Tools::SubjectCombinator::with_grid(subject_vec.clone(), grid);
+    let catalog = web::Data::new(Catalog::new(&subject_vec));
     let data_string = Subject::zipped_json(&subject_vec);
 
     let server = HttpServer::new(move || {
         App::new()
         .wrap(Logger::default())
         .data(combinator.clone())
-        .data(conn_pool.clone())
+        .app_data(store.clone())
+        .app_data(catalog.clone())
+        .data(share_config.clone())
         .data(data_string.clone())
         .service(web::resource("/api/comb").route(web::post().to(combination)))
+        .service(web::resource("/api/comb/stream").route(web::post().to(combination_stream)))
         .service(web::resource("/api/share").route(web::post().to(db_access)))
         .service(web::resource("/api/data").route(web::post().to(data)))
     });
@@ -151,7 +438,7 @@ async fn main() -> std::io::Result<()> {
 #[cfg(target_os = "windows")]
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    std::env::set_var("RUST_LOG", "actix_web=info");
+    std::env::set_var("RUST_LOG", "actix_web=info,server=info");
     env_logger::init();
 
     use backend::Subject::Subject as Subject;
@@ -160,22 +447,24 @@ async fn main() -> std::io::Result<()> {
     let default_bind = "127.0.0.1:8088".to_string();
     let bind = args.get(1).unwrap_or(&default_bind);
 
-    let subject_vec: Vec<Subject>;
-
-    if Path::new("data.json").exists() {
-        subject_vec = Subject::load("data.json");
-    }
-    else {
+    if !Path::new("data.json").exists() {
         let a = crawler::SubjectQuery::new(2020).fall().undergraduate().send().await.unwrap();
-        subject_vec = a.to_subject_vector();
-        backend::Subject::Subject::save(&subject_vec, "data.json");
+        backend::Subject::Subject::save(&a.to_subject_vector(), "data.json");
+    }
+    // time bits are encoded again with the grid of the combinator.
+    let grid = grid_from_env();
+    let (subject_vec, skipped) = Subject::load_with_grid("data.json", &grid);
+    for (sub, e) in skipped.iter() {
+        warn!("Skip {}-{} ({}): {}", &sub.code, &sub.class_num, &sub.class_name, e);
     }
 
-    let conn_pool = r2d2::Pool::builder().build(
-        RedisConnectionManager::new("redis://192.168.219.100/").unwrap()
-    ).unwrap();
+    // SHARE_STORE: "memory", "sled:<path>" or "redis://<host>/"
+    let store_config = env::var("SHARE_STORE").unwrap_or("redis://192.168.219.100/".to_string());
+    let store: web::Data<Box<dyn ShareStore>> = web::Data::new(backend::DB::open_store(&store_config).unwrap());
+    let share_config = ShareConfig::from_env();
 
-    let combinator = backend::Tools::SubjectCombinator::new(subject_vec.clone());
+    let combinator = backend::Tools::SubjectCombinator::with_grid(subject_vec.clone(), grid);
+    let catalog = web::Data::new(Catalog::new(&subject_vec));
     let data_string = Subject::zipped_json(&subject_vec);
 
     let server = HttpServer::new(move || {
@@ -185,9 +474,12 @@ async fn main() -> std::io::Result<()> {
             Cors::new().send_wildcard().finish()
         )
         .data(combinator.clone())
-        .data(conn_pool.clone())
+        .app_data(store.clone())
+        .app_data(catalog.clone())
+        .data(share_config.clone())
         .data(data_string.clone())
         .service(web::resource("/api/comb").route(web::post().to(combination)))
+        .service(web::resource("/api/comb/stream").route(web::post().to(combination_stream)))
         .service(web::resource("/api/share").route(web::post().to(db_access)))
         .service(web::resource("/api/data").route(web::post().to(data)))
     });